@@ -1,12 +1,19 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Instant;
 
 use crate::factorio::installed::InstalledMod;
-use crate::factorio::types::{ModListEntry, ServerSettings};
+use crate::factorio::mod_settings::{ModSettings, SettingPrototype, SettingValue};
+use crate::factorio::api::Progress;
+use crate::factorio::credentials::{self, Credentials, Source};
+use crate::factorio::portal::SharedPortal;
+use crate::factorio::types::ModListEntry;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tab {
     Manage,
     Install,
+    Settings,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -14,6 +21,8 @@ pub enum ActiveBlock {
     ManageModList,
     InstallModList,
     InstallSearch,
+    SettingsList,
+    SettingsEdit,
     QuitPopup,
 }
 
@@ -32,18 +41,37 @@ pub struct App {
     pub install_selected: Option<usize>,
     pub manage_mods: Vec<ManageMod>,
     pub manage_selected: Option<usize>,
+    /// Startup and map settings of the installed mods.
+    pub settings: Vec<SettingPrototype>,
+    /// `None` until mod-settings.dat has been read.
+    pub mod_settings: Option<ModSettings>,
+    pub settings_selected: Option<usize>,
+    pub settings_input: String,
+    pub settings_dirty: bool,
     pub status_message: Option<(String, Instant)>,
     pub factorio_version: String,
-    pub server_settings: ServerSettings,
+    /// `None` when browsing read-only.
+    pub credentials: Option<Credentials>,
+    pub credentials_source: Option<Source>,
+    pub portal: SharedPortal,
     pub mods_dir: String,
+    pub lock_path: PathBuf,
     pub should_quit: bool,
     pub show_quit_popup: bool,
     pub loading: bool,
     pub installing: bool,
+    /// Downloads in progress while installing, in the order they started.
+    pub downloads: Vec<Progress>,
 }
 
 impl App {
-    pub fn new(factorio_version: String, server_settings: ServerSettings, mods_dir: String) -> Self {
+    pub fn new(
+        factorio_version: String,
+        credentials: Option<(Credentials, Source)>,
+        portal: SharedPortal,
+        mods_dir: String,
+        lock_path: PathBuf,
+    ) -> Self {
         App {
             tab: Tab::Manage,
             active_block: ActiveBlock::ManageModList,
@@ -52,14 +80,23 @@ impl App {
             install_selected: None,
             manage_mods: Vec::new(),
             manage_selected: None,
+            settings: Vec::new(),
+            mod_settings: None,
+            settings_selected: None,
+            settings_input: String::new(),
+            settings_dirty: false,
             status_message: None,
             factorio_version,
-            server_settings,
+            credentials_source: credentials.as_ref().map(|(_, s)| s.clone()),
+            credentials: credentials.map(|(c, _)| c),
+            portal,
             mods_dir,
+            lock_path,
             should_quit: false,
             show_quit_popup: false,
             loading: true,
             installing: false,
+            downloads: Vec::new(),
         }
     }
 
@@ -81,6 +118,15 @@ impl App {
         self.status_message = Some((msg, Instant::now()));
     }
 
+    /// Credentials for an action that downloads; without them the status bar
+    /// explains why the action is unavailable.
+    pub fn require_credentials(&mut self) -> Option<Credentials> {
+        if self.credentials.is_none() {
+            self.set_status(format!("Read-only: {}", credentials::MISSING_HINT));
+        }
+        self.credentials.clone()
+    }
+
     pub fn clear_expired_status(&mut self) {
         if let Some((_, time)) = &self.status_message {
             if time.elapsed().as_secs() >= 5 {
@@ -95,6 +141,48 @@ impl App {
             .any(|m| m.installed_mod.name == mod_name)
     }
 
+    /// Replace the installed mods with a fresh read from disk. Mods already
+    /// listed keep their enabled and pending flags; others get `new_state`.
+    pub fn replace_installed(
+        &mut self,
+        mods: Vec<InstalledMod>,
+        new_state: impl Fn(&InstalledMod) -> (bool, bool),
+    ) {
+        let prev_state: HashMap<String, (bool, bool)> = self
+            .manage_mods
+            .iter()
+            .map(|m| (m.installed_mod.name.clone(), (m.enabled, m.pending)))
+            .collect();
+
+        self.manage_mods = mods
+            .into_iter()
+            .map(|m| {
+                let (enabled, pending) = prev_state
+                    .get(&m.name)
+                    .copied()
+                    .unwrap_or_else(|| new_state(&m));
+                ManageMod {
+                    installed_mod: m,
+                    enabled,
+                    pending,
+                }
+            })
+            .collect();
+
+        if !self.manage_mods.is_empty() && self.manage_selected.is_none() {
+            self.manage_selected = Some(0);
+        }
+    }
+
+    /// Current value of a setting: the one stored in mod-settings.dat, else
+    /// the default from settings.lua.
+    pub fn setting_value(&self, proto: &SettingPrototype) -> Option<SettingValue> {
+        self.mod_settings
+            .as_ref()
+            .and_then(|s| s.value(proto))
+            .or_else(|| proto.default.clone())
+    }
+
     /// Returns manage_mods indices in visual order: saved mods first, then pending.
     pub fn manage_display_order(&self) -> Vec<usize> {
         let mut order: Vec<usize> = Vec::new();
@@ -133,6 +221,13 @@ impl App {
                     }
                 }
             }
+            ActiveBlock::SettingsList => {
+                if let Some(sel) = self.settings_selected {
+                    if sel > 0 {
+                        self.settings_selected = Some(sel - 1);
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -163,15 +258,30 @@ impl App {
                     }
                 }
             }
+            ActiveBlock::SettingsList => {
+                let len = self.settings.len();
+                if len > 0 {
+                    let sel = self.settings_selected.unwrap_or(0);
+                    if sel < len - 1 {
+                        self.settings_selected = Some(sel + 1);
+                    }
+                }
+            }
             _ => {}
         }
     }
 
     pub fn select_tab(&mut self, tab: Tab) {
         self.tab = tab;
-        match tab {
-            Tab::Manage => self.active_block = ActiveBlock::ManageModList,
-            Tab::Install => self.active_block = ActiveBlock::InstallModList,
+        self.active_block = self.tab_block();
+    }
+
+    /// The block that has focus when `tab` is shown.
+    pub fn tab_block(&self) -> ActiveBlock {
+        match self.tab {
+            Tab::Manage => ActiveBlock::ManageModList,
+            Tab::Install => ActiveBlock::InstallModList,
+            Tab::Settings => ActiveBlock::SettingsList,
         }
     }
 }
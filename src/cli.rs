@@ -0,0 +1,547 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::config::FmlConfig;
+use crate::factorio::credentials::{self, Credentials};
+use crate::factorio::doctor::{self, Severity};
+use crate::factorio::graph;
+use crate::factorio::installer::{self, RemoveMode};
+use crate::factorio::lockfile::{self, Lockfile};
+use crate::factorio::manifest::{self, Plan};
+use crate::factorio::outdated::{self, Status};
+use crate::factorio::portal::{ModPortal, SharedPortal};
+use crate::factorio::save;
+use crate::factorio::store;
+use crate::factorio::stub_portal;
+use crate::factorio::transaction::Transaction;
+use crate::factorio::{installed, mod_list::ModList, types};
+
+/// Credentials for commands that download mods, checked against the portal.
+async fn credentials(config: &FmlConfig, portal: &dyn ModPortal) -> Result<Credentials> {
+    let (creds, source) = config.credentials()?.context(credentials::MISSING_HINT)?;
+    if portal.is_offline() {
+        return Ok(creds);
+    }
+    match portal.validate_credentials(&creds).await {
+        Ok(true) => eprintln!("Using Factorio credentials from {}", source),
+        Ok(false) => bail!(
+            "The mod portal rejected the Factorio credentials from {}",
+            source
+        ),
+        Err(e) => eprintln!(
+            "Warning: could not validate the credentials from {}: {}",
+            source, e
+        ),
+    }
+    Ok(creds)
+}
+
+/// `fml install <mod>...` — install mods and their dependencies without the TUI.
+///
+/// Every spec is attempted even if an earlier one fails; the command only
+/// errors (and thus exits non-zero) after the summary has been printed. A
+/// failed spec leaves nothing behind, and if mod-list.json or fml.lock cannot
+/// be written every install is rolled back.
+pub async fn install(config: &FmlConfig, portal: &SharedPortal, specs: &[String]) -> Result<()> {
+    let creds = credentials(config, &**portal).await?;
+    let factorio_version = config.factorio_version()?;
+    eprintln!("Detected Factorio version: {}", factorio_version);
+
+    let mods_dir = &config.mods_dir_path;
+    let mut installed_map = installed::read_installed_versions(mods_dir)?;
+    let mut mod_list = ModList::load_or_create(mods_dir)?;
+    let mut failed = 0;
+    let mut tx = Transaction::begin(mods_dir)?;
+
+    for spec in specs {
+        let spec: types::ModSpec = match spec.parse() {
+            Ok(s) => s,
+            Err(e) => {
+                println!("✘ {}: {}", spec, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let result = installer::install(
+            &mut tx,
+            portal,
+            &spec.name,
+            &spec.version_req,
+            &factorio_version,
+            &creds,
+            &installed_map,
+        )
+        .await;
+
+        match result {
+            Ok(downloaded) if downloaded.is_empty() => {
+                println!("= {}: already installed", spec.name);
+            }
+            Ok(downloaded) => {
+                for (name, release) in &downloaded {
+                    installed_map.insert(name.clone(), release.version.clone());
+                    mod_list.set_enabled(name, true);
+                }
+                let (_, release) = &downloaded[downloaded.len() - 1];
+                let dep_count = downloaded.len() - 1;
+                if dep_count > 0 {
+                    println!(
+                        "✔ {} {} (+ {} dependencies)",
+                        spec.name, release.version, dep_count
+                    );
+                } else {
+                    println!("✔ {} {}", spec.name, release.version);
+                }
+            }
+            Err(e) => {
+                println!("✘ {}: {}", spec.name, e);
+                failed += 1;
+            }
+        }
+    }
+
+    // Keep what installed unless recording it fails
+    let result = tx
+        .save_mod_list(&mod_list)
+        .and_then(|()| lockfile::write(mods_dir, &config.lock_path()));
+    tx.finish(result)?;
+
+    if failed > 0 {
+        bail!("{} of {} mods failed to install", failed, specs.len());
+    }
+    Ok(())
+}
+
+/// `fml install --locked` — reproduce exactly the mod set recorded in fml.lock.
+pub async fn install_locked(config: &FmlConfig, portal: &SharedPortal) -> Result<()> {
+    let lock = Lockfile::load(&config.lock_path())?;
+    let creds = credentials(config, &**portal).await?;
+
+    let report = installer::install_locked(portal, &lock, &creds, &config.mods_dir_path).await?;
+
+    for (name, release) in &report.downloaded {
+        println!("✔ {} {}", name, release.version);
+    }
+    for m in &report.removed {
+        println!("- removed {} {}", m.name, m.version);
+    }
+    println!("{} mod(s) already matched fml.lock", report.unchanged);
+    Ok(())
+}
+
+/// `fml sync` — download every mod listed in mod-list.json that is missing
+/// from the mods directory.
+pub async fn sync(config: &FmlConfig, portal: &SharedPortal) -> Result<()> {
+    let creds = credentials(config, &**portal).await?;
+    let factorio_version = config.factorio_version()?;
+    eprintln!("Detected Factorio version: {}", factorio_version);
+
+    let report = installer::sync(
+        portal,
+        &factorio_version,
+        &creds,
+        &config.mods_dir_path,
+        &config.lock_path(),
+    )
+    .await?;
+
+    for (name, release) in &report.downloaded {
+        println!("✔ {} {}", name, release.version);
+    }
+    for (name, reason) in &report.failed {
+        println!("✘ {}: {}", name, reason);
+    }
+
+    if !report.failed.is_empty() {
+        bail!(
+            "{} mod(s) from mod-list.json could not be installed",
+            report.failed.len()
+        );
+    }
+    if report.downloaded.is_empty() {
+        println!("Everything in mod-list.json is already installed");
+    }
+    Ok(())
+}
+
+/// `fml update [mod...]` — upgrade mods (all of them when none are given) to
+/// their newest release for the server's Factorio version.
+pub async fn update(config: &FmlConfig, portal: &SharedPortal, names: &[String]) -> Result<()> {
+    let creds = credentials(config, &**portal).await?;
+    let factorio_version = config.factorio_version()?;
+    eprintln!("Detected Factorio version: {}", factorio_version);
+
+    let report = installer::update(
+        portal,
+        names,
+        &factorio_version,
+        &creds,
+        &config.mods_dir_path,
+        &config.lock_path(),
+    )
+    .await?;
+
+    for upgrade in &report.upgraded {
+        println!(
+            "✔ {} {} -> {}",
+            upgrade.name, upgrade.from, upgrade.to.version
+        );
+    }
+    for (name, release) in &report.dependencies {
+        println!("+ {} {} (new dependency)", name, release.version);
+    }
+    for held in &report.held_back {
+        println!(
+            "! {} {} held back: required by {}",
+            held.name, held.latest, held.required_by
+        );
+    }
+    for (name, reason) in &report.failed {
+        println!("✘ {}: {}", name, reason);
+    }
+
+    if !report.failed.is_empty() {
+        bail!("{} mod(s) could not be updated", report.failed.len());
+    }
+    if report.upgraded.is_empty() {
+        println!("All mods are up to date");
+    }
+    Ok(())
+}
+
+/// `fml outdated` — list installed mods with a newer compatible release,
+/// using batched namelist requests rather than one request per mod.
+pub async fn outdated(config: &FmlConfig, portal: &SharedPortal, json: bool) -> Result<()> {
+    let factorio_version = config.factorio_version()?;
+    let installed: Vec<installed::InstalledMod> =
+        installed::read_installed_mods(&config.mods_dir_path)?
+            .into_iter()
+            .filter(|m| !types::is_builtin_mod(&m.name))
+            .collect();
+
+    let names: Vec<String> = installed.iter().map(|m| m.name.clone()).collect();
+    let portal_mods = portal.fetch_mods_by_name(&names).await?;
+    let entries = outdated::check(&installed, &portal_mods, &factorio_version);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    eprintln!("Detected Factorio version: {}", factorio_version);
+    let name_width = entries
+        .iter()
+        .map(|e| e.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!(
+        "{:<name_width$}  {:<10}  {:<10}",
+        "Mod", "Installed", "Latest"
+    );
+    for entry in &entries {
+        let latest = entry
+            .latest
+            .as_ref()
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let note = match entry.status {
+            Status::UpToDate => "",
+            Status::Outdated => "update available",
+            Status::NoCompatibleRelease => "no release for this Factorio version",
+            Status::NotOnPortal => "not on the mod portal",
+        };
+        println!(
+            "{:<name_width$}  {:<10}  {:<10}  {}",
+            entry.name,
+            entry.installed.to_string(),
+            latest,
+            note
+        );
+    }
+    Ok(())
+}
+
+/// `fml plan` — show what `fml apply` would change.
+pub async fn plan(config: &FmlConfig, portal: &SharedPortal) -> Result<()> {
+    let plan = compute_plan(config, portal).await?;
+    print_plan(&plan);
+    Ok(())
+}
+
+/// `fml apply` — make the mods directory match the `mods` declared in fml.json.
+pub async fn apply(config: &FmlConfig, portal: &SharedPortal) -> Result<()> {
+    let creds = credentials(config, &**portal).await?;
+    let plan = compute_plan(config, portal).await?;
+    print_plan(&plan);
+    if plan.is_empty() {
+        return Ok(());
+    }
+
+    installer::apply(
+        portal,
+        &plan,
+        &creds,
+        &config.mods_dir_path,
+        &config.lock_path(),
+    )
+    .await?;
+    println!("Applied {} change(s)", plan.actions.len());
+    Ok(())
+}
+
+async fn compute_plan(config: &FmlConfig, portal: &SharedPortal) -> Result<Plan> {
+    if config.mods.is_empty() {
+        bail!("No mods declared in fml.json. Add a \"mods\" list to use plan/apply.");
+    }
+
+    let factorio_version = config.factorio_version()?;
+    eprintln!("Detected Factorio version: {}", factorio_version);
+    let installed = installed::read_installed_mods(&config.mods_dir_path)?;
+    let mod_list = ModList::load_or_create(&config.mods_dir_path)?;
+
+    manifest::plan(
+        &config.mods,
+        &installed,
+        &mod_list,
+        &factorio_version,
+        &**portal,
+    )
+    .await
+}
+
+fn print_plan(plan: &Plan) {
+    if plan.is_empty() {
+        println!("Mods directory already matches fml.json");
+        return;
+    }
+    for action in &plan.actions {
+        println!("{}", action);
+    }
+}
+
+/// `fml doctor` — report inconsistencies that would stop Factorio from
+/// loading the installed mods. Fails if any of them is fatal.
+pub fn doctor(config: &FmlConfig) -> Result<()> {
+    let factorio_version = config.factorio_version()?;
+    let installed = installed::read_installed_mods(&config.mods_dir_path)?;
+    let mod_list = ModList::load_or_create(&config.mods_dir_path)?;
+
+    let issues = doctor::check(&installed, &mod_list, &factorio_version);
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!("{} fatal problem(s) found", errors);
+    }
+    if issues.is_empty() {
+        println!(
+            "No problems found in {} installed mod(s) for Factorio {}",
+            installed.len(),
+            factorio_version
+        );
+    }
+    Ok(())
+}
+
+/// `fml from-save <save.zip>` — install exactly the mods a save was made with.
+pub async fn from_save(config: &FmlConfig, portal: &SharedPortal, save_path: &Path) -> Result<()> {
+    let creds = credentials(config, &**portal).await?;
+    let factorio_version = config.factorio_version()?;
+    eprintln!("Detected Factorio version: {}", factorio_version);
+
+    let mods = save::read_save_mods(save_path)?;
+    println!(
+        "{} uses {} mod(s)",
+        save_path.display(),
+        mods.iter()
+            .filter(|m| !types::is_builtin_mod(&m.name))
+            .count()
+    );
+
+    let report = installer::install_save(
+        portal,
+        &mods,
+        &factorio_version,
+        &creds,
+        &config.mods_dir_path,
+        &config.lock_path(),
+    )
+    .await?;
+
+    for (name, release) in &report.downloaded {
+        println!("✔ {} {}", name, release.version);
+    }
+    for m in &report.replaced {
+        println!("- {} {}", m.name, m.version);
+    }
+    if report.unchanged > 0 {
+        println!(
+            "= {} mod(s) already installed in the right version",
+            report.unchanged
+        );
+    }
+    println!("mod-list.json now enables exactly the mods of the save");
+    Ok(())
+}
+
+/// `fml tree [mod]` — print the dependency tree of the installed mods.
+pub fn tree(config: &FmlConfig, root: Option<&str>) -> Result<()> {
+    let installed = installed::read_installed_mods(&config.mods_dir_path)?;
+    print!("{}", graph::tree(&installed, root)?);
+    Ok(())
+}
+
+/// `fml why <mod>` — show every chain of installed mods that requires `name`.
+pub fn why(config: &FmlConfig, name: &str) -> Result<()> {
+    let installed = installed::read_installed_mods(&config.mods_dir_path)?;
+    let chains = graph::why(&installed, name);
+
+    if chains.is_empty() {
+        println!("{} is not required by any installed mod", name);
+        return Ok(());
+    }
+    println!("{} is required by:", name);
+    for chain in chains {
+        println!("  {}", chain.join(" -> "));
+    }
+    Ok(())
+}
+
+/// `fml remove <mod>...` — delete mods and drop them from mod-list.json.
+pub fn remove(config: &FmlConfig, names: &[String], mode: RemoveMode) -> Result<()> {
+    let mods_dir = &config.mods_dir_path;
+    let mods = installed::read_installed_mods(mods_dir)?;
+    let mod_list = ModList::load_or_create(mods_dir)?;
+
+    let plan = installer::plan_removal(&mods, &mod_list, names, mode)?;
+
+    installer::remove(&plan, mods_dir, &config.lock_path())?;
+    for m in &plan {
+        println!("✔ removed {} {}", m.name, m.version);
+    }
+    Ok(())
+}
+
+/// `fml cache prune` — delete zips from the shared store that are not in the
+/// mods directory of any instance.
+pub fn cache_prune(config: &FmlConfig) -> Result<()> {
+    let store = config
+        .zip_store()
+        .context("No cache directory: set cache_dir in fml.json")?;
+
+    let mut keep = HashSet::new();
+    for mods_dir in config.all_mods_dirs()? {
+        keep.extend(store::referenced(Path::new(&mods_dir))?);
+    }
+
+    let removed = store.prune(&keep)?;
+    let freed: u64 = removed.iter().map(|(_, size)| size).sum();
+    for (path, _) in &removed {
+        println!("- {}", path.display());
+    }
+    println!(
+        "Removed {} unreferenced zip(s), freed {:.1} MiB",
+        removed.len(),
+        freed as f64 / (1024.0 * 1024.0)
+    );
+    Ok(())
+}
+
+/// `fml stub-portal` — serve a directory laid out like the mod portal
+/// (`api/mods.json`, `api/mods/<name>/full.json`, `download/<name>/<version>.zip`)
+/// until Ctrl-C, to test against offline.
+pub async fn stub_portal(root: &Path, addr: std::net::SocketAddr) -> Result<()> {
+    if !root.join("api").is_dir() {
+        bail!("{} has no api directory to serve", root.display());
+    }
+    let stub = stub_portal::serve_at(root.to_path_buf(), addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    eprintln!(
+        "Serving {} at {}; add it to \"mirrors\" in fml.json. Ctrl-C stops it.",
+        root.display(),
+        stub.url
+    );
+    tokio::signal::ctrl_c()
+        .await
+        .context("Failed to wait for Ctrl-C")?;
+    eprintln!("Answered {} request(s)", stub.requests());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::fake_portal::MemoryPortal;
+    use std::sync::Arc;
+
+    /// A config for `<dir>/mods` whose secrets file holds user/token.
+    fn make_config(dir: &Path) -> FmlConfig {
+        std::fs::create_dir_all(dir.join("mods")).unwrap();
+        std::fs::write(
+            dir.join("secrets.json"),
+            r#"{"username": "user", "token": "token"}"#,
+        )
+        .unwrap();
+        let config = serde_json::json!({
+            "mods_dir_path": dir.join("mods"),
+            "server_config_path": dir.join("server-settings.json"),
+            "factorio_version": "1.1",
+            "secrets_path": dir.join("secrets.json"),
+        });
+        let path = dir.join("fml.json");
+        std::fs::write(&path, config.to_string()).unwrap();
+        FmlConfig::load(Some(&path), None).unwrap()
+    }
+
+    fn make_portal() -> MemoryPortal {
+        MemoryPortal::new()
+            .with_release("lib", "1.0.0", &["base >= 1.1.0"])
+            .with_release("mod-a", "1.0.0", &["base >= 1.1.0", "lib >= 1.0.0"])
+    }
+
+    #[tokio::test]
+    async fn install_downloads_enables_and_locks() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = make_config(dir.path());
+        let portal: SharedPortal = Arc::new(make_portal());
+
+        install(&config, &portal, &["mod-a".to_string()])
+            .await
+            .unwrap();
+
+        let mods = dir.path().join("mods");
+        assert!(mods.join("lib_1.0.0.zip").is_file());
+        assert!(mods.join("mod-a_1.0.0.zip").is_file());
+        let mod_list = ModList::load_or_create(&config.mods_dir_path).unwrap();
+        assert!(mod_list.is_enabled("lib") && mod_list.is_enabled("mod-a"));
+        let lock = Lockfile::load(&config.lock_path()).unwrap();
+        assert_eq!(lock.mods.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn install_stops_on_rejected_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = make_config(dir.path());
+        let portal: SharedPortal = Arc::new(make_portal().accepting(Credentials {
+            username: "someone-else".to_string(),
+            token: "token".to_string(),
+        }));
+
+        let err = install(&config, &portal, &["mod-a".to_string()])
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("rejected the Factorio credentials"), "{}", err);
+        assert!(!dir.path().join("mods").join("mod-a_1.0.0.zip").exists());
+        assert!(!config.lock_path().exists());
+    }
+}
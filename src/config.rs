@@ -1,71 +1,733 @@
 use anyhow::{bail, Context, Result};
-use serde::{Deserialize, Serialize};
-use std::io::{self, Write};
-use std::path::Path;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::factorio::api::{Mirror, Portal};
+use crate::factorio::cache::Cache;
+use crate::factorio::credentials::{self, Credentials};
+use crate::factorio::http::{HttpClient, HttpSettings};
+use crate::factorio::manifest::ManifestMod;
+use crate::factorio::portal::SharedPortal;
+use crate::factorio::store::{LinkMode, ZipStore};
+use crate::factorio::types;
 
 const CONFIG_FILE: &str = "fml.json";
+const LOCK_FILE: &str = "fml.lock";
+
+/// Install roots probed by `fml init`: the usual headless tarball location
+/// and the volume of the factoriotools Docker image.
+const DISCOVERY_ROOTS: [&str; 2] = ["/opt/factorio", "/factorio"];
+
+/// Paths and settings of one Factorio server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Instance {
+    pub mods_dir_path: String,
+    pub server_config_path: String,
+    /// Overrides the version detected from the install next to the mods directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factorio_version: Option<String>,
+    /// JSON file with `username` and `token`, kept out of fml.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets_path: Option<String>,
+    /// `player-data.json` of a logged-in client to take credentials from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_data_path: Option<String>,
+    /// Declared mod set for `fml plan` / `fml apply`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mods: Vec<ManifestMod>,
+}
+
+/// On-disk layout of `fml.json`: an optional unnamed instance at the top
+/// level, plus any number of named ones, and the mod portal settings shared
+/// by all of them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ConfigFile {
+    #[serde(flatten, deserialize_with = "default_instance")]
+    default: Option<Instance>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mirrors: Vec<Mirror>,
+    /// Where portal responses are cached (default `$XDG_CACHE_HOME/fml`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache_dir: Option<String>,
+    /// How zips from the shared store are put into mods directories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    store_link: Option<LinkMode>,
+    /// Downloads running at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    download_concurrency: Option<usize>,
+    /// Timeouts, retries and proxy for talking to the mod portal.
+    #[serde(default, skip_serializing_if = "HttpSettings::is_default")]
+    http: HttpSettings,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    instances: BTreeMap<String, Instance>,
+}
+
+/// Keys that make the top level of `fml.json` an instance of its own.
+const INSTANCE_FIELDS: [&str; 6] = [
+    "mods_dir_path",
+    "server_config_path",
+    "factorio_version",
+    "secrets_path",
+    "player_data_path",
+    "mods",
+];
+
+/// The top-level instance, if any of its keys are there. A flattened
+/// `Option` would turn a malformed one into `None` and hide the error.
+fn default_instance<'de, D>(deserializer: D) -> Result<Option<Instance>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let fields = serde_json::Map::deserialize(deserializer)?;
+    if !INSTANCE_FIELDS.iter().any(|f| fields.contains_key(*f)) {
+        return Ok(None);
+    }
+    Instance::deserialize(serde_json::Value::Object(fields))
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl ConfigFile {
+    fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn select(mut self, instance: Option<&str>) -> Result<Instance> {
+        let names = self
+            .instances
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        match instance {
+            Some(name) => match self.instances.remove(name) {
+                Some(found) => Ok(found),
+                None if self.instances.is_empty() => {
+                    bail!("Instance '{}' not found: no instances are configured", name)
+                }
+                None => bail!("Instance '{}' not found (available: {})", name, names),
+            },
+            None => match self.default {
+                Some(default) => Ok(default),
+                None if self.instances.is_empty() => {
+                    bail!("No mods_dir_path configured. Run `fml init` first.")
+                }
+                None => bail!(
+                    "No default instance configured; pick one with --instance ({})",
+                    names
+                ),
+            },
+        }
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The selected instance of a loaded `fml.json`.
+#[derive(Debug)]
 pub struct FmlConfig {
     pub mods_dir_path: String,
     pub server_config_path: String,
+    pub factorio_version: Option<String>,
+    pub secrets_path: Option<String>,
+    pub player_data_path: Option<String>,
+    pub mods: Vec<ManifestMod>,
+    /// Mod portals to use, in order; empty means the official portal.
+    pub mirrors: Vec<Mirror>,
+    pub cache_dir: Option<PathBuf>,
+    pub store_link: LinkMode,
+    pub download_concurrency: Option<usize>,
+    pub http: HttpSettings,
+    /// Browse and resolve from the cache only (`--offline`).
+    pub offline: bool,
+    /// The `fml.json` this was loaded from.
+    path: PathBuf,
+    instance: Option<String>,
 }
 
 impl FmlConfig {
-    pub fn init() -> Result<()> {
-        let mods_dir = prompt("Path to Factorio mods directory")?;
-        let mods_path = Path::new(&mods_dir);
-        if !mods_path.is_dir() {
-            bail!("Mods directory does not exist: {}", mods_dir);
+    /// Write the config file (`config_path`, or `./fml.json`). Paths not
+    /// passed as flags (or their environment variables) are discovered from
+    /// common headless layouts and proposed interactively; with `assume_yes`
+    /// or without a terminal the discovered paths are used as-is.
+    ///
+    /// An existing file is updated in place, so other instances and the
+    /// declared mods survive a re-init.
+    pub fn init(
+        config_path: Option<&Path>,
+        instance: Option<&str>,
+        mods_dir: Option<String>,
+        server_settings: Option<String>,
+        assume_yes: bool,
+    ) -> Result<()> {
+        let interactive = !assume_yes && io::stdin().is_terminal();
+        let found = discover(&DISCOVERY_ROOTS.map(PathBuf::from), home_dir().as_deref());
+
+        let mods_dir = match mods_dir {
+            Some(dir) => dir,
+            None => choose(
+                "Path to Factorio mods directory",
+                found.mods_dir.as_deref(),
+                interactive,
+                "--mods-dir",
+            )?,
+        };
+        let server_config = match server_settings {
+            Some(path) => path,
+            None => choose(
+                "Path to server-settings.json",
+                found.server_settings.as_deref(),
+                interactive,
+                "--server-settings",
+            )?,
+        };
+
+        let (mods_dir_path, server_config_path) = validate_paths(&mods_dir, &server_config)?;
+
+        let path = config_path.unwrap_or(Path::new(CONFIG_FILE));
+        let mut file = if path.exists() {
+            ConfigFile::read(path)?
+        } else {
+            ConfigFile::default()
+        };
+        let slot = match instance {
+            Some(name) => file.instances.get_mut(name),
+            None => file.default.as_mut(),
+        };
+        match slot {
+            Some(existing) => {
+                existing.mods_dir_path = mods_dir_path;
+                existing.server_config_path = server_config_path;
+            }
+            None => {
+                let new = Instance {
+                    mods_dir_path,
+                    server_config_path,
+                    factorio_version: None,
+                    secrets_path: None,
+                    player_data_path: None,
+                    mods: Vec::new(),
+                };
+                match instance {
+                    Some(name) => {
+                        file.instances.insert(name.to_string(), new);
+                    }
+                    None => file.default = Some(new),
+                }
+            }
         }
 
-        let server_config = prompt("Path to server-settings.json")?;
-        let server_path = Path::new(&server_config);
-        if !server_path.is_file() {
-            bail!("Server settings file does not exist: {}", server_config);
+        let json = serde_json::to_string_pretty(&file)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("Configuration saved to {}", path.display());
+        Ok(())
+    }
+
+    /// Load `config_path`, or else the first `fml.json` found in the current
+    /// directory and then in `$XDG_CONFIG_HOME/fml/`, and select `instance`
+    /// (the top-level settings when `None`).
+    pub fn load(config_path: Option<&Path>, instance: Option<&str>) -> Result<Self> {
+        let path = match config_path {
+            Some(path) => {
+                if !path.is_file() {
+                    bail!("Config file not found: {}", path.display());
+                }
+                path.to_path_buf()
+            }
+            None => locate()?,
+        };
+
+        let file = ConfigFile::read(&path)?;
+        let mirrors = file.mirrors.clone();
+        let cache_dir = file.cache_dir.as_ref().map(PathBuf::from);
+        let store_link = file.store_link.unwrap_or_default();
+        let download_concurrency = file.download_concurrency;
+        let http = file.http.clone();
+        let selected = file.select(instance)?;
+        Ok(FmlConfig {
+            mods_dir_path: selected.mods_dir_path,
+            server_config_path: selected.server_config_path,
+            factorio_version: selected.factorio_version,
+            secrets_path: selected.secrets_path,
+            player_data_path: selected.player_data_path,
+            mods: selected.mods,
+            mirrors,
+            cache_dir,
+            store_link,
+            download_concurrency,
+            http,
+            offline: false,
+            path,
+            instance: instance.map(str::to_string),
+        })
+    }
+
+    /// The configured Factorio version, or the one detected from the install.
+    pub fn factorio_version(&self) -> Result<String> {
+        match &self.factorio_version {
+            // Only major.minor matters for matching releases
+            Some(version) => Ok(version.split('.').take(2).collect::<Vec<_>>().join(".")),
+            None => types::detect_factorio_version(&self.mods_dir_path),
         }
+    }
 
-        let config = FmlConfig {
-            mods_dir_path: mods_path
-                .canonicalize()
-                .context("Failed to canonicalize mods directory path")?
-                .to_string_lossy()
-                .into_owned(),
-            server_config_path: server_path
-                .canonicalize()
-                .context("Failed to canonicalize server config path")?
-                .to_string_lossy()
-                .into_owned(),
+    /// Find Factorio credentials, trying in order the `FML_USERNAME` /
+    /// `FML_TOKEN` environment variables, the secrets file, server-settings.json
+    /// and a client's player-data.json (the configured one, else next to the
+    /// mods directory or in `~/.factorio`).
+    pub fn credentials(&self) -> Result<Option<(Credentials, credentials::Source)>> {
+        let player_data = match &self.player_data_path {
+            Some(path) => vec![PathBuf::from(path)],
+            None => {
+                let mut candidates = vec![Path::new(&self.mods_dir_path)
+                    .join("..")
+                    .join("player-data.json")];
+                if let Some(home) = home_dir() {
+                    candidates.push(home.join(".factorio").join("player-data.json"));
+                }
+                candidates
+            }
         };
 
-        let json = serde_json::to_string_pretty(&config)?;
-        std::fs::write(CONFIG_FILE, json).context("Failed to write fml.json")?;
-        println!("Configuration saved to {}", CONFIG_FILE);
-        Ok(())
+        credentials::Lookup {
+            env_username: std::env::var(credentials::USERNAME_ENV).ok(),
+            env_token: std::env::var(credentials::TOKEN_ENV).ok(),
+            secrets_file: self.secrets_path.as_ref().map(PathBuf::from),
+            server_settings: Some(PathBuf::from(&self.server_config_path)),
+            player_data,
+        }
+        .find()
+    }
+
+    /// The configured cache directory, else `$XDG_CACHE_HOME/fml`.
+    pub fn cache_root(&self) -> Option<PathBuf> {
+        self.cache_dir.clone().or_else(Cache::default_dir)
+    }
+
+    /// The zip store shared by all instances, inside the cache directory.
+    pub fn zip_store(&self) -> Option<ZipStore> {
+        self.cache_root()
+            .map(|dir| ZipStore::new(dir.join("zips"), self.store_link))
+    }
+
+    /// The mod portal client for the configured mirrors, backed by the cache
+    /// and the zip store. Fails on an invalid proxy URL.
+    pub fn portal(&self) -> Result<Portal> {
+        let mut portal = Portal::new(self.mirrors.clone())
+            .with_http(HttpClient::new(&self.http)?)
+            .with_offline(self.offline);
+        if let Some(concurrency) = self.download_concurrency {
+            portal = portal.with_concurrency(concurrency);
+        }
+        if let Some(dir) = self.cache_root() {
+            portal = portal.with_cache(Cache::new(dir));
+        }
+        if let Some(store) = self.zip_store() {
+            portal = portal.with_store(store);
+        }
+        Ok(portal)
+    }
+
+    /// [`FmlConfig::portal`], ready to be handed to commands and tasks.
+    pub fn shared_portal(&self) -> Result<SharedPortal> {
+        Ok(Arc::new(self.portal()?))
+    }
+
+    /// Mods directories of every instance in the config file, this one
+    /// included.
+    pub fn all_mods_dirs(&self) -> Result<Vec<String>> {
+        let file = ConfigFile::read(&self.path)?;
+        let mut dirs: Vec<String> = file
+            .default
+            .into_iter()
+            .chain(file.instances.into_values())
+            .map(|i| i.mods_dir_path)
+            .collect();
+        if !dirs.contains(&self.mods_dir_path) {
+            dirs.push(self.mods_dir_path.clone());
+        }
+        Ok(dirs)
     }
 
-    pub fn load() -> Result<Self> {
-        let path = Path::new(CONFIG_FILE);
-        if !path.exists() {
-            bail!(
-                "No fml.json found in current directory. Run `fml init` first."
-            );
+    /// Where the lock file lives: next to `fml.json`, one per instance.
+    pub fn lock_path(&self) -> PathBuf {
+        let dir = self.path.parent().unwrap_or(Path::new(""));
+        match &self.instance {
+            Some(name) => dir.join(format!("fml.{}.lock", name)),
+            None => dir.join(LOCK_FILE),
         }
-        let contents = std::fs::read_to_string(path).context("Failed to read fml.json")?;
-        let config: FmlConfig =
-            serde_json::from_str(&contents).context("Failed to parse fml.json")?;
-        Ok(config)
     }
 }
 
-fn prompt(message: &str) -> Result<String> {
-    print!("{}: ", message);
+/// Check that both paths exist and return their canonical forms.
+fn validate_paths(mods_dir: &str, server_config: &str) -> Result<(String, String)> {
+    let mods_path = Path::new(mods_dir);
+    if !mods_path.is_dir() {
+        bail!("Mods directory does not exist: {}", mods_dir);
+    }
+
+    let server_path = Path::new(server_config);
+    if !server_path.is_file() {
+        bail!("Server settings file does not exist: {}", server_config);
+    }
+
+    Ok((
+        mods_path
+            .canonicalize()
+            .context("Failed to canonicalize mods directory path")?
+            .to_string_lossy()
+            .into_owned(),
+        server_path
+            .canonicalize()
+            .context("Failed to canonicalize server config path")?
+            .to_string_lossy()
+            .into_owned(),
+    ))
+}
+
+fn xdg_config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(".config")))
+}
+
+fn locate() -> Result<PathBuf> {
+    let local = PathBuf::from(CONFIG_FILE);
+    if local.is_file() {
+        return Ok(local);
+    }
+    let global = xdg_config_dir().map(|dir| dir.join("fml").join(CONFIG_FILE));
+    match global {
+        Some(global) if global.is_file() => Ok(global),
+        Some(global) => bail!(
+            "No fml.json found in the current directory or at {}. Run `fml init` first.",
+            global.display()
+        ),
+        None => bail!("No fml.json found in current directory. Run `fml init` first."),
+    }
+}
+
+fn prompt(message: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(default) => print!("{} [{}]: ", message, default),
+        None => print!("{}: ", message),
+    }
     io::stdout().flush()?;
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
     let trimmed = input.trim().to_string();
     if trimmed.is_empty() {
-        bail!("Input cannot be empty");
+        return match default {
+            Some(default) => Ok(default.to_string()),
+            None => bail!("Input cannot be empty"),
+        };
     }
     Ok(trimmed)
 }
+
+fn choose(message: &str, found: Option<&Path>, interactive: bool, flag: &str) -> Result<String> {
+    let found = found.map(|p| p.to_string_lossy().into_owned());
+    if interactive {
+        return prompt(message, found.as_deref());
+    }
+    match found {
+        Some(path) => {
+            println!("{}: {}", message, path);
+            Ok(path)
+        }
+        None => bail!("{}: nothing found, pass {}", message, flag),
+    }
+}
+
+// --- Layout discovery ---
+
+#[derive(Debug, Default, PartialEq)]
+struct Discovered {
+    mods_dir: Option<PathBuf>,
+    server_settings: Option<PathBuf>,
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+/// Probe each install root for a mods directory and a server-settings.json.
+/// Both come from the same root, so two installs on one host are never
+/// mixed: the first root that has both wins, else the first that has either.
+fn discover(roots: &[PathBuf], home: Option<&Path>) -> Discovered {
+    let mut probed: Vec<Discovered> = roots
+        .iter()
+        .map(|root| {
+            let write_dir = write_data_dir(root, home);
+            let mods = write_dir.join("mods");
+            Discovered {
+                mods_dir: mods.is_dir().then_some(mods),
+                server_settings: [
+                    root.join("data").join("server-settings.json"),
+                    write_dir.join("config").join("server-settings.json"),
+                    root.join("config").join("server-settings.json"),
+                ]
+                .into_iter()
+                .find(|p| p.is_file()),
+            }
+        })
+        .collect();
+
+    let complete = probed
+        .iter()
+        .position(|d| d.mods_dir.is_some() && d.server_settings.is_some());
+    let partial = probed
+        .iter()
+        .position(|d| d.mods_dir.is_some() || d.server_settings.is_some());
+    complete
+        .or(partial)
+        .map(|i| probed.swap_remove(i))
+        .unwrap_or_default()
+}
+
+/// The directory Factorio writes mods and saves into, as set by the
+/// `config-path.cfg` next to the install. Without one (or with the default
+/// portable layout) that is the install root itself.
+fn write_data_dir(root: &Path, home: Option<&Path>) -> PathBuf {
+    let cfg = match std::fs::read_to_string(root.join("config-path.cfg")) {
+        Ok(cfg) => cfg,
+        Err(_) => return root.to_path_buf(),
+    };
+
+    let system_dirs = cfg.lines().any(|line| {
+        line.split_once('=').is_some_and(|(key, value)| {
+            key.trim() == "use-system-read-write-data-directories" && value.trim() == "true"
+        })
+    });
+
+    match home {
+        Some(home) if system_dirs => home.join(".factorio"),
+        _ => root.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "{}").unwrap();
+    }
+
+    #[test]
+    fn discovers_tarball_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("factorio");
+        std::fs::create_dir_all(root.join("mods")).unwrap();
+        touch(&root.join("data").join("server-settings.json"));
+
+        let found = discover(&[dir.path().join("missing"), root.clone()], None);
+        assert_eq!(found.mods_dir, Some(root.join("mods")));
+        assert_eq!(
+            found.server_settings,
+            Some(root.join("data").join("server-settings.json"))
+        );
+    }
+
+    #[test]
+    fn discovers_docker_volume() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("mods")).unwrap();
+        touch(&dir.path().join("config").join("server-settings.json"));
+
+        let found = discover(&[dir.path().to_path_buf()], None);
+        assert_eq!(found.mods_dir, Some(dir.path().join("mods")));
+        assert_eq!(
+            found.server_settings,
+            Some(dir.path().join("config").join("server-settings.json"))
+        );
+    }
+
+    #[test]
+    fn takes_both_paths_from_one_install() {
+        let dir = tempfile::tempdir().unwrap();
+        // A tarball install with mods but no settings yet, and a complete Docker volume
+        let tarball = dir.path().join("opt");
+        std::fs::create_dir_all(tarball.join("mods")).unwrap();
+        let docker = dir.path().join("factorio");
+        std::fs::create_dir_all(docker.join("mods")).unwrap();
+        touch(&docker.join("config").join("server-settings.json"));
+
+        let found = discover(&[tarball.clone(), docker.clone()], None);
+        assert_eq!(found.mods_dir, Some(docker.join("mods")));
+        assert_eq!(
+            found.server_settings,
+            Some(docker.join("config").join("server-settings.json"))
+        );
+
+        // Both complete: the first root wins for both paths
+        touch(&tarball.join("data").join("server-settings.json"));
+        let found = discover(&[tarball.clone(), docker], None);
+        assert_eq!(found.mods_dir, Some(tarball.join("mods")));
+        assert_eq!(
+            found.server_settings,
+            Some(tarball.join("data").join("server-settings.json"))
+        );
+    }
+
+    #[test]
+    fn follows_config_path_cfg_to_system_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("factorio");
+        let home = dir.path().join("home");
+        std::fs::create_dir_all(home.join(".factorio").join("mods")).unwrap();
+        std::fs::create_dir_all(root.join("mods")).unwrap();
+        std::fs::write(
+            root.join("config-path.cfg"),
+            "config-path=__PATH__system-write-data__/config\nuse-system-read-write-data-directories=true\n",
+        )
+        .unwrap();
+
+        let found = discover(&[root], Some(&home));
+        assert_eq!(found.mods_dir, Some(home.join(".factorio").join("mods")));
+        assert_eq!(found.server_settings, None);
+    }
+
+    #[test]
+    fn validates_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("server-settings.json");
+        touch(&settings);
+        let mods = dir.path().to_string_lossy().into_owned();
+
+        assert!(validate_paths(&mods, &settings.to_string_lossy()).is_ok());
+        assert!(validate_paths(&mods, &mods).is_err());
+        assert!(validate_paths("/nonexistent/mods", &settings.to_string_lossy()).is_err());
+    }
+
+    const MULTI: &str = r#"{
+        "mods_dir_path": "/srv/main/mods",
+        "server_config_path": "/srv/main/server-settings.json",
+        "mirrors": [
+            { "url": "http://mods.internal", "download_url": "http://files.internal" },
+            { "url": "https://mods.factorio.com" }
+        ],
+        "http": { "read_timeout": 120, "proxy": "http://proxy.internal:3128" },
+        "instances": {
+            "pvp": {
+                "mods_dir_path": "/srv/pvp/mods",
+                "server_config_path": "/srv/pvp/server-settings.json",
+                "factorio_version": "1.1.110",
+                "mods": [{ "name": "pvp-helper" }]
+            }
+        }
+    }"#;
+
+    #[test]
+    fn selects_default_and_named_instances() {
+        let file: ConfigFile = serde_json::from_str(MULTI).unwrap();
+        let main = file.select(None).unwrap();
+        assert_eq!(main.mods_dir_path, "/srv/main/mods");
+        assert!(main.factorio_version.is_none());
+
+        let file: ConfigFile = serde_json::from_str(MULTI).unwrap();
+        let pvp = file.select(Some("pvp")).unwrap();
+        assert_eq!(pvp.mods_dir_path, "/srv/pvp/mods");
+        assert_eq!(pvp.mods[0].name, "pvp-helper");
+
+        let file: ConfigFile = serde_json::from_str(MULTI).unwrap();
+        let err = file.select(Some("coop")).unwrap_err().to_string();
+        assert!(err.contains("available: pvp"), "{}", err);
+    }
+
+    #[test]
+    fn instances_without_default() {
+        let file: ConfigFile = serde_json::from_str(
+            r#"{"instances": {"a": {"mods_dir_path": "/a", "server_config_path": "/a.json"}}}"#,
+        )
+        .unwrap();
+        assert!(file.default.is_none());
+        let err = file.select(None).unwrap_err().to_string();
+        assert!(err.contains("--instance (a)"), "{}", err);
+    }
+
+    #[test]
+    fn lock_file_per_instance_next_to_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fml.json");
+        std::fs::write(&path, MULTI).unwrap();
+
+        let main = FmlConfig::load(Some(&path), None).unwrap();
+        assert_eq!(main.lock_path(), dir.path().join("fml.lock"));
+
+        let pvp = FmlConfig::load(Some(&path), Some("pvp")).unwrap();
+        assert_eq!(pvp.lock_path(), dir.path().join("fml.pvp.lock"));
+        assert_eq!(pvp.factorio_version().unwrap(), "1.1");
+        assert_eq!(
+            pvp.all_mods_dirs().unwrap(),
+            vec!["/srv/main/mods", "/srv/pvp/mods"]
+        );
+    }
+
+    #[test]
+    fn mirrors_are_shared_by_all_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fml.json");
+        std::fs::write(&path, MULTI).unwrap();
+
+        let pvp = FmlConfig::load(Some(&path), Some("pvp")).unwrap();
+        let portal = pvp.portal().unwrap();
+        let urls: Vec<&str> = portal.mirrors().iter().map(|m| m.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec!["http://mods.internal", "https://mods.factorio.com"]
+        );
+        assert_eq!(
+            pvp.mirrors[0].download_url.as_deref(),
+            Some("http://files.internal")
+        );
+        assert_eq!(pvp.http.read_timeout, 120);
+        assert_eq!(
+            pvp.http.connect_timeout,
+            HttpSettings::default().connect_timeout
+        );
+        assert_eq!(
+            pvp.http.proxy.as_deref(),
+            Some("http://proxy.internal:3128")
+        );
+
+        let file: ConfigFile =
+            serde_json::from_str(r#"{"mods_dir_path": "/m", "server_config_path": "/s.json"}"#)
+                .unwrap();
+        assert!(file.mirrors.is_empty());
+        assert!(file.http.is_default());
+        let urls: Vec<String> = Portal::new(file.mirrors)
+            .mirrors()
+            .iter()
+            .map(|m| m.url.clone())
+            .collect();
+        assert_eq!(urls, vec![crate::factorio::api::OFFICIAL_URL.to_string()]);
+    }
+
+    #[test]
+    fn reports_errors_in_the_top_level_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fml.json");
+        std::fs::write(
+            &path,
+            r#"{"mods_dir_path": 42, "server_config_path": "/s.json"}"#,
+        )
+        .unwrap();
+        let err = format!("{:#}", FmlConfig::load(Some(&path), None).unwrap_err());
+        assert!(err.contains("Failed to parse"), "{}", err);
+        assert!(err.contains("invalid type"), "{}", err);
+        assert!(!err.contains("fml init"), "{}", err);
+
+        let file: Result<ConfigFile, _> =
+            serde_json::from_str(r#"{"mods_dir_pth": "/m", "server_config_path": "/s.json"}"#);
+        let err = file.unwrap_err().to_string();
+        assert!(err.contains("missing field `mods_dir_path`"), "{}", err);
+    }
+}
@@ -3,8 +3,11 @@ use crossterm::event::{self, Event, KeyEvent};
 use std::time::Duration;
 use tokio::sync::mpsc;
 
+use crate::factorio::api::Progress;
 use crate::factorio::installed::InstalledMod;
+use crate::factorio::installer::{SyncReport, UpdateReport};
 use crate::factorio::mod_list::ModList;
+use crate::factorio::mod_settings::{ModSettings, SettingPrototype};
 use crate::factorio::types::ModListEntry;
 
 #[derive(Debug)]
@@ -14,8 +17,17 @@ pub enum AppEvent {
     ModListLoaded(Result<Vec<ModListEntry>>),
     ModInstalled(Result<InstallResult>),
     ModDeleted(Result<String>),
+    ModsSynced(Result<SyncResult>),
+    ModsUpdated(Result<UpdateResult>),
+    /// Bytes received for the download in progress.
+    DownloadProgress(Progress),
     InstalledModsLoaded(Result<(Vec<InstalledMod>, ModList)>),
-    Error(String),
+    /// Result of checking the credentials against the portal at startup.
+    CredentialsChecked(Result<bool>),
+    SettingsLoaded(Result<(Vec<SettingPrototype>, ModSettings)>),
+    /// Something went wrong that does not stop the app, e.g. the mod list
+    /// coming from a stale cache.
+    Warning(String),
 }
 
 #[derive(Debug)]
@@ -25,6 +37,19 @@ pub struct InstallResult {
     pub installed_mods: Vec<InstalledMod>,
 }
 
+#[derive(Debug)]
+pub struct SyncResult {
+    pub report: SyncReport,
+    pub installed_mods: Vec<InstalledMod>,
+    pub mod_list: ModList,
+}
+
+#[derive(Debug)]
+pub struct UpdateResult {
+    pub report: UpdateReport,
+    pub installed_mods: Vec<InstalledMod>,
+}
+
 pub fn spawn_event_loop(tx: mpsc::UnboundedSender<AppEvent>) {
     tokio::spawn(async move {
         let tick_rate = Duration::from_millis(250);
@@ -1,11 +1,319 @@
 use anyhow::{bail, Context, Result};
-use serde::Deserialize;
-use std::io::Write;
-use std::path::Path;
+use reqwest::header::{self, HeaderMap};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 
+use super::cache::{self, Cache};
+use super::credentials::Credentials;
+use super::http::{self, HttpClient};
+use super::portal::{BoxFuture, ModPortal, NotFound, Reporter, SharedPortal};
+use super::store::ZipStore;
 use super::types::{Dependency, Mod, ModListEntry, Release};
 
-const BASE_URL: &str = "https://mods.factorio.com";
+pub const OFFICIAL_URL: &str = "https://mods.factorio.com";
+
+/// Downloads running at once unless configured otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Requests per download before giving up; each one resumes the last.
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// Bytes between two progress reports of one download.
+const PROGRESS_STEP: u64 = 256 * 1024;
+
+/// Mod names per `namelist` request, keeping the URL well below server limits.
+const NAMELIST_BATCH: usize = 50;
+
+// --- Mirrors ---
+
+/// One mod portal to talk to. `download_url` is where release zips are
+/// fetched from; it defaults to `url`, which serves the JSON API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mirror {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+}
+
+impl Mirror {
+    pub fn new(url: &str) -> Self {
+        Mirror {
+            url: url.to_string(),
+            download_url: None,
+        }
+    }
+
+    fn api_base(&self) -> &str {
+        self.url.trim_end_matches('/')
+    }
+
+    fn download_base(&self) -> &str {
+        self.download_url
+            .as_deref()
+            .unwrap_or(&self.url)
+            .trim_end_matches('/')
+    }
+}
+
+/// The mod portal and its mirrors, tried in order.
+///
+/// A request moves on to the next mirror when a mirror cannot be reached or
+/// answers with an error status, after [`HttpClient`] has retried transient
+/// failures. When every mirror answered, the last answer is returned so
+/// callers can still report e.g. a 404.
+///
+/// With a [`Cache`], the mod list and mod details are served from disk while
+/// fresh, and with a [`ZipStore`] releases already downloaded for any
+/// instance are reused. Offline, only cached data and stored zips are used.
+/// When no mirror can be reached, a stale cache entry is used with a warning.
+#[derive(Debug, Clone)]
+pub struct Portal {
+    mirrors: Vec<Mirror>,
+    http: HttpClient,
+    cache: Option<Cache>,
+    store: Option<ZipStore>,
+    progress: Option<ProgressFn>,
+    warnings: Option<WarnFn>,
+    concurrency: usize,
+    offline: bool,
+}
+
+/// How far one download has got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub file_name: String,
+    pub downloaded: u64,
+    /// From `Content-Length`, when the server sent one.
+    pub total: Option<u64>,
+}
+
+/// Callback receiving download progress.
+#[derive(Clone)]
+struct ProgressFn(Reporter);
+
+impl std::fmt::Debug for ProgressFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressFn")
+    }
+}
+
+/// Callback receiving warnings that do not stop a request.
+#[derive(Clone)]
+struct WarnFn(Arc<dyn Fn(String) + Send + Sync>);
+
+impl std::fmt::Debug for WarnFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("WarnFn")
+    }
+}
+
+/// Outcome of one request in [`Portal::download_mod`].
+enum Attempt {
+    /// The body was received in full; holds the SHA-1 of the whole file.
+    Complete(String),
+    /// The transfer broke off; the partial file is kept.
+    Interrupted(anyhow::Error),
+}
+
+/// Outcome of [`Portal::get_cached`].
+enum Fetched {
+    Body(String),
+    Status(reqwest::StatusCode),
+    /// Offline and not in the cache.
+    NotCached,
+}
+
+impl Default for Portal {
+    fn default() -> Self {
+        Portal::new(Vec::new())
+    }
+}
+
+impl Portal {
+    /// Use `mirrors` in order, or the official portal when there are none.
+    pub fn new(mirrors: Vec<Mirror>) -> Self {
+        let mirrors = if mirrors.is_empty() {
+            vec![Mirror::new(OFFICIAL_URL)]
+        } else {
+            mirrors
+        };
+        Portal {
+            mirrors,
+            http: HttpClient::default(),
+            cache: None,
+            store: None,
+            progress: None,
+            warnings: None,
+            concurrency: DEFAULT_CONCURRENCY,
+            offline: false,
+        }
+    }
+
+    pub fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_store(mut self, store: ZipStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Report the progress of every download to `progress`.
+    pub fn with_progress(mut self, progress: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(ProgressFn(Arc::new(progress)));
+        self
+    }
+
+    /// Send warnings to `warn` instead of stderr.
+    pub fn with_warnings(mut self, warn: impl Fn(String) + Send + Sync + 'static) -> Self {
+        self.warnings = Some(WarnFn(Arc::new(warn)));
+        self
+    }
+
+    /// How many downloads may run at once; at least one.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn mirrors(&self) -> &[Mirror] {
+        &self.mirrors
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    async fn get(
+        &self,
+        headers: HeaderMap,
+        url_for: impl Fn(&Mirror) -> Result<reqwest::Url>,
+    ) -> Result<reqwest::Response> {
+        let mut last_response = None;
+        let mut errors = Vec::new();
+
+        for mirror in &self.mirrors {
+            match self.http.get(url_for(mirror)?, headers.clone()).await {
+                Ok(resp)
+                    if resp.status().is_success()
+                        || resp.status() == reqwest::StatusCode::NOT_MODIFIED =>
+                {
+                    return Ok(resp)
+                }
+                Ok(resp) => last_response = Some(resp),
+                // The URL may carry credentials; the mirror says enough
+                Err(e) => errors.push(format!("{}: {}", mirror.url, e.without_url())),
+            }
+        }
+
+        match last_response {
+            Some(resp) => Ok(resp),
+            None => bail!("No mod portal reachable ({})", errors.join("; ")),
+        }
+    }
+
+    /// The body stored under `key`, from the cache while it is younger than
+    /// `ttl` (or at all when offline), else from the portal. A stale entry is
+    /// revalidated with its ETag.
+    async fn get_cached(
+        &self,
+        key: &str,
+        ttl: Duration,
+        url_for: impl Fn(&Mirror) -> Result<reqwest::Url>,
+    ) -> Result<Fetched> {
+        let mut cached = self.cache.as_ref().and_then(|c| c.get(key));
+        match &cached {
+            Some(entry) if self.offline || entry.is_fresh(ttl) => {
+                return Ok(Fetched::Body(entry.body.clone()))
+            }
+            None if self.offline => return Ok(Fetched::NotCached),
+            _ => {}
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = cached.as_ref().and_then(|e| e.etag.as_deref()) {
+            headers.insert(header::IF_NONE_MATCH, etag.parse()?);
+        }
+        let resp = match self.get(headers, url_for).await {
+            Ok(resp) => resp,
+            Err(e) => match cached {
+                Some(entry) => return Ok(self.stale(entry, e)),
+                None => return Err(e),
+            },
+        };
+        let status = resp.status();
+        if http::is_transient(status) {
+            if let Some(entry) = cached.take() {
+                let reason = format!("Mod portal unavailable (HTTP {})", status);
+                return Ok(self.stale(entry, reason));
+            }
+        }
+
+        if status == reqwest::StatusCode::NOT_MODIFIED {
+            if let (Some(cache), Some(entry)) = (&self.cache, cached) {
+                // A cache that cannot be written only costs the next request
+                let body = entry.body.clone();
+                let _ = cache.touch(key, entry);
+                return Ok(Fetched::Body(body));
+            }
+        }
+        if !status.is_success() {
+            return Ok(Fetched::Status(status));
+        }
+
+        let etag = resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = resp.text().await?;
+        if let Some(cache) = &self.cache {
+            let _ = cache.put(key, etag, body.clone());
+        }
+        Ok(Fetched::Body(body))
+    }
+
+    /// Serve `entry`, however old, because the portal could not answer.
+    fn stale(&self, entry: cache::Entry, reason: impl std::fmt::Display) -> Fetched {
+        self.warn(format!("{}; using cached data", reason));
+        Fetched::Body(entry.body)
+    }
+
+    fn warn(&self, message: String) {
+        match &self.warnings {
+            Some(warn) => (warn.0)(message),
+            None => eprintln!("Warning: {}", message),
+        }
+    }
+
+    /// The cached mod list for `factorio_version`, however old, so the TUI can
+    /// show something while the real one loads.
+    pub fn cached_mod_list(&self, factorio_version: &str) -> Option<Vec<ModListEntry>> {
+        let entry = self
+            .cache
+            .as_ref()?
+            .get(&cache::mod_list_key(factorio_version))?;
+        parse_mod_list(&entry.body).ok()
+    }
+}
 
 // --- JSON response structures ---
 
@@ -19,17 +327,16 @@ struct ApiModEntry {
     name: String,
     title: String,
     downloads_count: u64,
-    #[serde(default)]
-    summary: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamelistResponse {
+    results: Vec<ApiModFull>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ApiModFull {
     name: String,
-    title: String,
-    #[serde(default)]
-    summary: String,
-    downloads_count: u64,
     #[serde(default)]
     releases: Vec<ApiRelease>,
 }
@@ -51,29 +358,38 @@ struct ApiInfoJson {
     dependencies: Vec<String>,
 }
 
-// --- Public API functions ---
-
-pub async fn fetch_mod_list(factorio_version: &str) -> Result<Vec<ModListEntry>> {
-    let url = format!(
-        "{}/api/mods?page_size=max&hide_deprecated=true&version={}",
-        BASE_URL, factorio_version
-    );
-
-    let resp = reqwest::get(&url)
-        .await
-        .context("Failed to fetch mod list from Factorio mod portal")?;
+fn into_mod(body: ApiModFull) -> Mod {
+    let releases: Vec<Release> = body
+        .releases
+        .into_iter()
+        .filter_map(|r| {
+            let version = semver::Version::parse(&r.version).ok()?;
+            let dependencies: Vec<Dependency> = r
+                .info_json
+                .dependencies
+                .iter()
+                .filter_map(|d| d.parse().ok())
+                .collect();
+            Some(Release {
+                download_url: r.download_url,
+                file_name: r.file_name,
+                version,
+                factorio_version: r.info_json.factorio_version,
+                sha1: r.sha1,
+                dependencies,
+            })
+        })
+        .collect();
 
-    if !resp.status().is_success() {
-        bail!(
-            "Mod portal returned HTTP {} when fetching mod list",
-            resp.status()
-        );
+    Mod {
+        name: body.name,
+        releases,
     }
+}
 
-    let body: ModListResponse = resp
-        .json()
-        .await
-        .context("Failed to parse mod list response")?;
+pub fn parse_mod_list(body: &str) -> Result<Vec<ModListEntry>> {
+    let body: ModListResponse =
+        serde_json::from_str(body).context("Failed to parse mod list response")?;
 
     let mut entries: Vec<ModListEntry> = body
         .results
@@ -82,146 +398,471 @@ pub async fn fetch_mod_list(factorio_version: &str) -> Result<Vec<ModListEntry>>
             name: e.name,
             title: e.title,
             downloads_count: e.downloads_count,
-            summary: e.summary,
         })
         .collect();
 
+    #[allow(clippy::unnecessary_sort_by)]
     entries.sort_by(|a, b| b.downloads_count.cmp(&a.downloads_count));
     Ok(entries)
 }
 
-pub async fn fetch_mod_details(name: &str) -> Result<Mod> {
-    let url = format!("{}/api/mods/{}/full", BASE_URL, name);
+/// A `/api/mods/<name>/full` response.
+pub fn parse_mod_details(body: &str) -> Result<Mod> {
+    let body: ApiModFull = serde_json::from_str(body)?;
+    Ok(into_mod(body))
+}
 
-    let resp = reqwest::get(&url)
-        .await
-        .with_context(|| format!("Failed to fetch details for mod '{}'", name))?;
+// --- Public API functions ---
 
-    if resp.status().as_u16() == 404 {
-        bail!("Mod '{}' not found on the mod portal", name);
+impl Portal {
+    pub async fn fetch_mod_list(&self, factorio_version: &str) -> Result<Vec<ModListEntry>> {
+        let fetched = self
+            .get_cached(&cache::mod_list_key(factorio_version), cache::MOD_LIST_TTL, |m| {
+                Ok(reqwest::Url::parse_with_params(
+                    &format!("{}/api/mods", m.api_base()),
+                    &[
+                        ("page_size", "max"),
+                        ("hide_deprecated", "true"),
+                        ("version", factorio_version),
+                    ],
+                )?)
+            })
+            .await
+            .context("Failed to fetch mod list from Factorio mod portal")?;
+
+        match fetched {
+            Fetched::Body(body) => parse_mod_list(&body),
+            Fetched::Status(status) => {
+                bail!("Mod portal returned HTTP {} when fetching mod list", status)
+            }
+            Fetched::NotCached => bail!(
+                "No cached mod list for Factorio {}; run fml once without --offline",
+                factorio_version
+            ),
+        }
     }
 
-    if !resp.status().is_success() {
-        bail!(
-            "Mod portal returned HTTP {} when fetching mod '{}'",
-            resp.status(),
-            name
-        );
+    pub async fn fetch_mod_details(&self, name: &str) -> Result<Mod> {
+        let fetched = self
+            .get_cached(&cache::details_key(name), cache::DETAILS_TTL, |m| {
+                Ok(format!("{}/api/mods/{}/full", m.api_base(), name).parse()?)
+            })
+            .await
+            .with_context(|| format!("Failed to fetch details for mod '{}'", name))?;
+
+        let body = match fetched {
+            Fetched::Body(body) => body,
+            Fetched::Status(status) if status.as_u16() == 404 => {
+                return Err(NotFound(name.to_string()).into())
+            }
+            Fetched::Status(status) => bail!(
+                "Mod portal returned HTTP {} when fetching mod '{}'",
+                status,
+                name
+            ),
+            Fetched::NotCached => bail!("Mod '{}' is not in the cache (offline mode)", name),
+        };
+
+        parse_mod_details(&body)
+            .with_context(|| format!("Failed to parse details for mod '{}'", name))
     }
 
-    let body: ApiModFull = resp
-        .json()
-        .await
-        .with_context(|| format!("Failed to parse details for mod '{}'", name))?;
+    /// Fetch several mods via the `namelist` filter, [`NAMELIST_BATCH`] names
+    /// per request.
+    ///
+    /// Releases returned this way carry no dependency information. Mods unknown
+    /// to the portal are simply missing from the result. Offline, every mod
+    /// must be in the cache.
+    pub async fn fetch_mods_by_name(&self, names: &[String]) -> Result<Vec<Mod>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
 
-    let releases: Vec<Release> = body
-        .releases
-        .into_iter()
-        .filter_map(|r| {
-            let version = semver::Version::parse(&r.version).ok()?;
-            let dependencies: Vec<Dependency> = r
-                .info_json
-                .dependencies
-                .iter()
-                .filter_map(|d| d.parse().ok())
-                .collect();
-            Some(Release {
-                download_url: r.download_url,
-                file_name: r.file_name,
-                version,
-                factorio_version: r.info_json.factorio_version,
-                sha1: r.sha1,
-                dependencies,
+        // Offline, the cached details stand in for the namelist query
+        if self.offline {
+            let Some(cache) = &self.cache else {
+                bail!("Offline mode: no cache to look up {} mods in", names.len());
+            };
+            let mut mods = Vec::new();
+            let mut missing = Vec::new();
+            for name in names {
+                match cache
+                    .get(&cache::details_key(name))
+                    .and_then(|entry| serde_json::from_str::<ApiModFull>(&entry.body).ok())
+                {
+                    Some(m) => mods.push(into_mod(m)),
+                    None => missing.push(name.as_str()),
+                }
+            }
+            if !missing.is_empty() {
+                bail!("Not in the cache (offline mode): {}", missing.join(", "));
+            }
+            return Ok(mods);
+        }
+
+        let mut mods = Vec::new();
+        for batch in names.chunks(NAMELIST_BATCH) {
+            let namelist = batch.join(",");
+            let resp = self
+                .get(HeaderMap::new(), |m| {
+                    reqwest::Url::parse_with_params(
+                        &format!("{}/api/mods", m.api_base()),
+                        &[("page_size", "max"), ("namelist", namelist.as_str())],
+                    )
+                    .context("Failed to build namelist URL")
+                })
+                .await
+                .context("Failed to fetch mods from Factorio mod portal")?;
+
+            if !resp.status().is_success() {
+                bail!(
+                    "Mod portal returned HTTP {} when fetching {} mods by name",
+                    resp.status(),
+                    batch.len()
+                );
+            }
+
+            let body: NamelistResponse = resp
+                .json()
+                .await
+                .context("Failed to parse namelist response")?;
+            mods.extend(body.results.into_iter().map(into_mod));
+        }
+        Ok(mods)
+    }
+
+    /// Check credentials against the authenticated bookmarks endpoint.
+    ///
+    /// `Ok(false)` means the portal rejected them; an error means it could not
+    /// be asked.
+    pub async fn validate_credentials(&self, credentials: &Credentials) -> Result<bool> {
+        if self.offline {
+            bail!("Offline mode: the mod portal was not asked");
+        }
+        let resp = self
+            .get(HeaderMap::new(), |m| {
+                Ok(reqwest::Url::parse_with_params(
+                    &format!("{}/api/bookmarks", m.api_base()),
+                    &[
+                        ("username", credentials.username.as_str()),
+                        ("token", credentials.token.as_str()),
+                    ],
+                )?)
             })
-        })
-        .collect();
+            .await
+            .context("Failed to reach the mod portal")?;
 
-    Ok(Mod {
-        name: body.name,
-        title: body.title,
-        summary: body.summary,
-        downloads_count: body.downloads_count,
-        releases,
-    })
-}
+        let status = resp.status();
+        if status.is_success() {
+            Ok(true)
+        } else if status.is_client_error() {
+            Ok(false)
+        } else {
+            bail!("Unexpected response from the mod portal (HTTP {})", status)
+        }
+    }
 
-pub async fn download_mod(
-    release: &Release,
-    username: &str,
-    token: &str,
-    dir: &str,
-) -> Result<()> {
-    let url = format!(
-        "{}{}?username={}&token={}",
-        BASE_URL, release.download_url, username, token
-    );
+    pub async fn download_mod(
+        &self,
+        release: &Release,
+        credentials: &Credentials,
+        dir: &str,
+    ) -> Result<()> {
+        let file_path = Path::new(dir).join(&release.file_name);
+        if let Some(store) = self.store.as_ref().filter(|_| !release.sha1.is_empty()) {
+            if store.place(&release.sha1, &file_path)? {
+                return Ok(());
+            }
+        }
 
-    let file_path = Path::new(dir).join(&release.file_name);
+        if self.offline {
+            bail!("Cannot download '{}' in offline mode", release.file_name);
+        }
 
-    let resp = reqwest::get(&url)
-        .await
-        .with_context(|| format!("Failed to download mod: {}", release.file_name))?;
+        // Interrupted transfers resume from the partial file, which is only
+        // dropped once it turns out not to match the release
+        let part_path = part_path(&file_path);
+        let mut attempt = 1;
+        loop {
+            let offset = tokio::fs::metadata(&part_path)
+                .await
+                .map(|m| m.len())
+                .unwrap_or(0);
+            match self.fetch_part(release, credentials, &part_path, offset).await? {
+                Attempt::Complete(digest) => match verify(release, &part_path, &digest) {
+                    Ok(()) => break,
+                    Err(e) => {
+                        let _ = tokio::fs::remove_file(&part_path).await;
+                        // The old partial file may have been the problem
+                        if offset == 0 || attempt == MAX_DOWNLOAD_ATTEMPTS {
+                            return Err(e);
+                        }
+                    }
+                },
+                Attempt::Interrupted(e) if attempt == MAX_DOWNLOAD_ATTEMPTS => {
+                    return Err(e.context(format!(
+                        "Download of '{}' was interrupted {} times; run again to resume it",
+                        release.file_name, attempt
+                    )));
+                }
+                Attempt::Interrupted(_) => {}
+            }
+            attempt += 1;
+        }
 
-    if !resp.status().is_success() {
-        bail!(
-            "Download failed for '{}' (HTTP {}): check username and token in server-settings.json",
-            release.file_name,
-            resp.status()
-        );
+        tokio::fs::rename(&part_path, &file_path)
+            .await
+            .with_context(|| format!("Failed to move download to {}", file_path.display()))?;
+        if let Some(store) = self.store.as_ref().filter(|_| !release.sha1.is_empty()) {
+            // Other instances just download it again if this fails
+            let _ = store.insert(&release.sha1, &file_path);
+        }
+        Ok(())
     }
 
-    let bytes = resp
-        .bytes()
-        .await
-        .with_context(|| format!("Failed to read download body for {}", release.file_name))?;
-
-    // Write to file
-    let mut file = std::fs::File::create(&file_path)
-        .with_context(|| format!("Failed to create file: {}", file_path.display()))?;
-    file.write_all(&bytes)
-        .with_context(|| format!("Failed to write file: {}", file_path.display()))?;
-    drop(file);
-
-    // Verify SHA1 checksum if provided
-    if !release.sha1.is_empty() {
-        use sha1_smol::Sha1;
-        let mut hasher = Sha1::new();
-        hasher.update(&bytes);
-        let digest = hasher.digest().to_string();
-        if digest != release.sha1 {
-            let _ = std::fs::remove_file(&file_path);
+    /// Download into `part_path`, continuing after its first `offset` bytes
+    /// when the server honours the `Range` request. The SHA-1 covers the
+    /// whole file either way.
+    async fn fetch_part(
+        &self,
+        release: &Release,
+        credentials: &Credentials,
+        part_path: &Path,
+        offset: u64,
+    ) -> Result<Attempt> {
+        let mut headers = HeaderMap::new();
+        if offset > 0 {
+            headers.insert(header::RANGE, format!("bytes={}-", offset).parse()?);
+        }
+        let mut resp = self
+            .get(headers, |m| {
+                Ok(reqwest::Url::parse_with_params(
+                    &format!("{}{}", m.download_base(), release.download_url),
+                    &[
+                        ("username", credentials.username.as_str()),
+                        ("token", credentials.token.as_str()),
+                    ],
+                )?)
+            })
+            .await
+            .with_context(|| format!("Failed to download mod: {}", release.file_name))?;
+
+        let status = resp.status();
+        if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial file is at least as long as the release
+            let _ = tokio::fs::remove_file(part_path).await;
+            return Ok(Attempt::Interrupted(anyhow::anyhow!(
+                "Partial download of '{}' was longer than the file",
+                release.file_name
+            )));
+        }
+        if !status.is_success() {
             bail!(
-                "SHA1 mismatch for '{}': expected {}, got {}",
+                "Download failed for '{}' (HTTP {}): check your Factorio username and token",
                 release.file_name,
-                release.sha1,
-                digest
+                status
             );
         }
+
+        let resumed = offset > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT;
+        let (mut hasher, mut file, mut downloaded, total) = if resumed {
+            // Re-hashing a large partial file must not hold up other tasks
+            let path = part_path.to_path_buf();
+            let hasher = tokio::task::spawn_blocking(move || hash_file(&path))
+                .await
+                .context("Failed to hash the partial download")??;
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(part_path)
+                .await
+                .with_context(|| format!("Failed to open {}", part_path.display()))?;
+            let total = content_range_total(&resp).or(resp.content_length().map(|l| l + offset));
+            (hasher, file, offset, total)
+        } else {
+            // No partial file, or the server sent the whole file anyway
+            let file = tokio::fs::File::create(part_path)
+                .await
+                .with_context(|| format!("Failed to create file: {}", part_path.display()))?;
+            (sha1_smol::Sha1::new(), file, 0, resp.content_length())
+        };
+
+        let mut reported = downloaded;
+        self.report(&release.file_name, downloaded, total);
+        loop {
+            let chunk = match resp.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = file.sync_all().await;
+                    let e = anyhow::Error::new(e.without_url());
+                    return Ok(Attempt::Interrupted(e.context(format!(
+                        "Failed to read download body for {}",
+                        release.file_name
+                    ))));
+                }
+            };
+            file.write_all(&chunk)
+                .await
+                .with_context(|| format!("Failed to write file: {}", part_path.display()))?;
+            hasher.update(&chunk);
+            downloaded += chunk.len() as u64;
+
+            if downloaded - reported >= PROGRESS_STEP {
+                reported = downloaded;
+                self.report(&release.file_name, downloaded, total);
+            }
+        }
+        file.sync_all()
+            .await
+            .with_context(|| format!("Failed to write file: {}", part_path.display()))?;
+        self.report(&release.file_name, downloaded, total);
+
+        Ok(Attempt::Complete(hasher.digest().to_string()))
     }
 
-    // Verify it's a valid zip
-    match zip::ZipArchive::new(std::fs::File::open(&file_path)?) {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            // Delete corrupt file
-            let _ = std::fs::remove_file(&file_path);
-            bail!(
-                "Downloaded file '{}' is not a valid zip archive: {}",
-                release.file_name,
-                e
-            );
+    fn report(&self, file_name: &str, downloaded: u64, total: Option<u64>) {
+        if let Some(progress) = &self.progress {
+            (progress.0)(Progress {
+                file_name: file_name.to_string(),
+                downloaded,
+                total,
+            });
         }
     }
 }
 
+impl ModPortal for Portal {
+    fn fetch_mod_list<'a>(
+        &'a self,
+        factorio_version: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ModListEntry>>> {
+        Box::pin(Portal::fetch_mod_list(self, factorio_version))
+    }
+
+    fn fetch_mod_details<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Mod>> {
+        Box::pin(Portal::fetch_mod_details(self, name))
+    }
+
+    fn fetch_mods_by_name<'a>(&'a self, names: &'a [String]) -> BoxFuture<'a, Result<Vec<Mod>>> {
+        Box::pin(Portal::fetch_mods_by_name(self, names))
+    }
+
+    fn download_mod<'a>(
+        &'a self,
+        release: &'a Release,
+        credentials: &'a Credentials,
+        dir: &'a str,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(Portal::download_mod(self, release, credentials, dir))
+    }
+
+    fn validate_credentials<'a>(
+        &'a self,
+        credentials: &'a Credentials,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(Portal::validate_credentials(self, credentials))
+    }
+
+    fn with_reporter(&self, report: Reporter) -> SharedPortal {
+        Arc::new(self.clone().with_progress(move |progress| report(progress)))
+    }
+
+    fn cached_mod_list(&self, factorio_version: &str) -> Option<Vec<ModListEntry>> {
+        Portal::cached_mod_list(self, factorio_version)
+    }
+
+    fn concurrency(&self) -> usize {
+        Portal::concurrency(self)
+    }
+
+    fn is_offline(&self) -> bool {
+        Portal::is_offline(self)
+    }
+}
+
+/// The full size from a `Content-Range: bytes 100-199/200` header.
+fn content_range_total(resp: &reqwest::Response) -> Option<u64> {
+    resp.headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Where a download is written before it has been verified.
+fn part_path(file_path: &Path) -> PathBuf {
+    let mut name = file_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    file_path.with_file_name(name)
+}
+
+/// The SHA-1 state after reading all of `path`, to go on hashing a resumed
+/// download.
+fn hash_file(path: &Path) -> Result<sha1_smol::Sha1> {
+    use std::io::Read;
+
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher)
+}
+
+/// Check a finished download against the release's SHA-1 (when the portal
+/// gave one) and make sure it is a zip.
+pub fn verify(release: &Release, path: &Path, digest: &str) -> Result<()> {
+    if !release.sha1.is_empty() && digest != release.sha1 {
+        bail!(
+            "SHA1 mismatch for '{}': expected {}, got {}",
+            release.file_name,
+            release.sha1,
+            digest
+        );
+    }
+
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if let Err(e) = zip::ZipArchive::new(file) {
+        bail!(
+            "Downloaded file '{}' is not a valid zip archive: {}",
+            release.file_name,
+            e
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::factorio::http::{HttpClient, HttpSettings};
+    use crate::factorio::store::LinkMode;
+    use crate::factorio::stub_portal;
+    use std::sync::Mutex;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            username: "user".to_string(),
+            token: "token".to_string(),
+        }
+    }
 
     #[tokio::test]
     #[ignore] // Hits the network
     async fn test_fetch_mod_list() {
-        let mods = fetch_mod_list("2.0").await.unwrap();
+        let mods = Portal::default().fetch_mod_list("2.0").await.unwrap();
         assert!(!mods.is_empty());
         println!("First 5 mods:");
         for m in mods.iter().take(5) {
@@ -232,9 +873,335 @@ mod tests {
     #[tokio::test]
     #[ignore] // Hits the network
     async fn test_fetch_mod_details() {
-        let m = fetch_mod_details("flib").await.unwrap();
+        let m = Portal::default().fetch_mod_details("flib").await.unwrap();
         assert_eq!(m.name, "flib");
         assert!(!m.releases.is_empty());
         println!("flib has {} releases", m.releases.len());
     }
+
+    #[tokio::test]
+    #[ignore] // Hits the network
+    async fn test_fetch_mods_by_name() {
+        let names = vec!["flib".to_string(), "does-not-exist-fml".to_string()];
+        let mods = Portal::default().fetch_mods_by_name(&names).await.unwrap();
+        assert_eq!(mods.len(), 1);
+        assert_eq!(mods[0].name, "flib");
+        assert!(!mods[0].releases.is_empty());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_next_mirror() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let empty = tempfile::tempdir().unwrap();
+        let empty_stub = stub_portal::serve(empty.path().to_path_buf()).await;
+        let portal = Portal::new(vec![
+            Mirror::new(&stub_portal::dead_url().await),
+            Mirror::new(&empty_stub.url),
+            Mirror::new(&format!("{}/", stub.url)),
+        ]);
+
+        let m = portal.fetch_mod_details("mod-a").await.unwrap();
+        assert_eq!(m.releases[0].dependencies.len(), 2);
+        let list = portal.fetch_mod_list("1.1").await.unwrap();
+        assert_eq!(list[0].name, "lib");
+
+        let err = portal.fetch_mod_details("nope").await.unwrap_err();
+        assert!(err.to_string().contains("not found"), "{}", err);
+
+        let down = Portal::new(vec![Mirror::new(&stub_portal::dead_url().await)]);
+        let err = down.fetch_mod_list("1.1").await.unwrap_err();
+        assert!(format!("{:#}", err).contains("No mod portal reachable"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn errors_do_not_reveal_the_token() {
+        let portal = Portal::new(vec![Mirror::new(&stub_portal::dead_url().await)]);
+        let credentials = Credentials {
+            token: "s3cret-token".to_string(),
+            ..credentials()
+        };
+        let release = Release {
+            download_url: "/download/lib/1".to_string(),
+            file_name: "lib_1.0.0.zip".to_string(),
+            version: semver::Version::new(1, 0, 0),
+            factorio_version: "1.1".to_string(),
+            sha1: String::new(),
+            dependencies: Vec::new(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let err = portal
+            .download_mod(&release, &credentials, dir.path().to_str().unwrap())
+            .await
+            .unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(err.contains("No mod portal reachable"), "{}", err);
+        assert!(!err.contains("s3cret-token"), "{}", err);
+
+        let err = portal.validate_credentials(&credentials).await.unwrap_err();
+        assert!(!format!("{:#}", err).contains("s3cret-token"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limited_requests() {
+        let stub = stub_portal::serve_rate_limited(stub_portal::fixture_root(), 2).await;
+        let portal = Portal::new(vec![Mirror::new(&stub.url)]);
+        let m = portal.fetch_mod_details("lib").await.unwrap();
+        assert_eq!(m.name, "lib");
+        assert_eq!(stub.requests(), 3);
+
+        let stub = stub_portal::serve_rate_limited(stub_portal::fixture_root(), 2).await;
+        let settings = HttpSettings {
+            retries: 1,
+            ..HttpSettings::default()
+        };
+        let impatient =
+            Portal::new(vec![Mirror::new(&stub.url)]).with_http(HttpClient::new(&settings).unwrap());
+        let err = impatient.fetch_mod_list("1.1").await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 429"), "{}", err);
+        assert_eq!(stub.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn downloads_from_separate_download_url() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let empty = tempfile::tempdir().unwrap();
+        let api_only = stub_portal::serve(empty.path().to_path_buf()).await;
+        let details = Portal::new(vec![Mirror::new(&stub.url)])
+            .fetch_mod_details("lib")
+            .await
+            .unwrap();
+
+        // The API host has no zips; downloads must go to download_url
+        let portal = Portal::new(vec![Mirror {
+            url: api_only.url.clone(),
+            download_url: Some(stub.url.clone()),
+        }]);
+        let credentials = credentials();
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        portal
+            .download_mod(&details.releases[0], &credentials, mods_dir)
+            .await
+            .unwrap();
+        assert!(dir.path().join("lib_1.0.0.zip").is_file());
+    }
+
+    #[tokio::test]
+    async fn caches_and_revalidates_with_etag() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf());
+        let portal = Portal::new(vec![Mirror::new(&stub.url)]).with_cache(cache.clone());
+
+        assert!(portal.cached_mod_list("1.1").is_none());
+        portal.fetch_mod_list("1.1").await.unwrap();
+        portal.fetch_mod_details("lib").await.unwrap();
+        portal.fetch_mod_details("lib").await.unwrap();
+        assert_eq!(stub.requests(), 2);
+        assert_eq!(portal.cached_mod_list("1.1").unwrap().len(), 2);
+
+        // Past its TTL the entry is revalidated and the 304 keeps the body
+        let key = cache::details_key("lib");
+        let mut entry = cache.get(&key).unwrap();
+        assert!(entry.etag.is_some());
+        entry.fetched_at = 0;
+        std::fs::write(
+            dir.path().join("mods/lib.json"),
+            serde_json::to_string(&entry).unwrap(),
+        )
+        .unwrap();
+        let m = portal.fetch_mod_details("lib").await.unwrap();
+        assert_eq!(m.name, "lib");
+        assert_eq!(stub.requests(), 3);
+        assert!(cache.get(&key).unwrap().is_fresh(cache::DETAILS_TTL));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_stale_cache_when_unreachable() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let url = stub.url.clone();
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf());
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        let portal = Portal::new(vec![Mirror::new(&url)])
+            .with_cache(cache.clone())
+            .with_warnings(move |w| sink.lock().unwrap().push(w));
+        portal.fetch_mod_details("lib").await.unwrap();
+        drop(stub);
+
+        let key = cache::details_key("lib");
+        let mut entry = cache.get(&key).unwrap();
+        entry.fetched_at = 0;
+        std::fs::write(
+            dir.path().join("mods/lib.json"),
+            serde_json::to_string(&entry).unwrap(),
+        )
+        .unwrap();
+
+        let m = portal.fetch_mod_details("lib").await.unwrap();
+        assert_eq!(m.name, "lib");
+        let warnings = warnings.lock().unwrap().clone();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("No mod portal reachable"), "{}", warnings[0]);
+        assert!(warnings[0].ends_with("using cached data"), "{}", warnings[0]);
+
+        // Nothing cached to fall back to
+        let err = portal.fetch_mod_details("mod-a").await.unwrap_err();
+        assert!(format!("{:#}", err).contains("No mod portal reachable"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn fetches_long_namelists_in_batches() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let portal = Portal::new(vec![Mirror::new(&stub.url)]);
+        let names: Vec<String> = (0..NAMELIST_BATCH * 2 + 1)
+            .map(|i| format!("mod-{}", i))
+            .collect();
+        portal.fetch_mods_by_name(&names).await.unwrap();
+        assert_eq!(stub.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn offline_uses_only_the_cache() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf());
+        let online = Portal::new(vec![Mirror::new(&stub.url)]).with_cache(cache.clone());
+        let lib = online.fetch_mod_details("lib").await.unwrap();
+
+        let offline = online.clone().with_offline(true);
+        assert_eq!(offline.fetch_mod_details("lib").await.unwrap().name, "lib");
+        let err = offline.fetch_mod_details("mod-a").await.unwrap_err();
+        assert!(err.to_string().contains("not in the cache"), "{}", err);
+        assert!(offline.fetch_mod_list("1.1").await.is_err());
+
+        let names = vec!["lib".to_string()];
+        assert_eq!(offline.fetch_mods_by_name(&names).await.unwrap().len(), 1);
+        let names = vec!["lib".to_string(), "mod-a".to_string()];
+        let err = offline.fetch_mods_by_name(&names).await.unwrap_err();
+        assert!(err.to_string().ends_with("mod-a"), "{}", err);
+        let uncached = Portal::default().with_offline(true);
+        assert!(uncached.fetch_mods_by_name(&names).await.is_err());
+
+        let credentials = credentials();
+        let mods_dir = dir.path().to_str().unwrap();
+        assert!(offline
+            .download_mod(&lib.releases[0], &credentials, mods_dir)
+            .await
+            .is_err());
+        assert_eq!(stub.requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn reuses_zips_from_the_store() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let dir = tempfile::tempdir().unwrap();
+        let store = ZipStore::new(dir.path().join("zips"), LinkMode::Hardlink);
+        let portal = Portal::new(vec![Mirror::new(&stub.url)]).with_store(store);
+        let credentials = credentials();
+        let lib = portal.fetch_mod_details("lib").await.unwrap();
+        let release = &lib.releases[0];
+
+        for instance in ["a", "b"] {
+            let mods_dir = dir.path().join(instance);
+            std::fs::create_dir_all(&mods_dir).unwrap();
+            portal
+                .download_mod(release, &credentials, mods_dir.to_str().unwrap())
+                .await
+                .unwrap();
+            assert!(mods_dir.join("lib_1.0.0.zip").is_file());
+        }
+        // One details request and a single download
+        assert_eq!(stub.requests(), 2);
+        assert!(dir.path().join(format!("zips/{}.zip", release.sha1)).is_file());
+
+        let mods_dir = dir.path().join("c");
+        std::fs::create_dir_all(&mods_dir).unwrap();
+        portal
+            .with_offline(true)
+            .download_mod(release, &credentials, mods_dir.to_str().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn streams_downloads_and_reports_progress() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let portal = Portal::new(vec![Mirror::new(&stub.url)])
+            .with_progress(move |p| sink.lock().unwrap().push(p));
+        let credentials = credentials();
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+
+        let lib = portal.fetch_mod_details("lib").await.unwrap();
+        portal
+            .download_mod(&lib.releases[0], &credentials, mods_dir)
+            .await
+            .unwrap();
+        let size = std::fs::metadata(dir.path().join("lib_1.0.0.zip")).unwrap().len();
+        let last = reports.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(last.file_name, "lib_1.0.0.zip");
+        assert_eq!((last.downloaded, last.total), (size, Some(size)));
+
+        // A bad hash leaves neither the zip nor the partial file behind
+        let mut bad = lib.releases[0].clone();
+        bad.file_name = "bad_1.0.0.zip".to_string();
+        bad.sha1 = "0".repeat(40);
+        let err = portal
+            .download_mod(&bad, &credentials, mods_dir)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("SHA1 mismatch"), "{}", err);
+        let mut names: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["lib_1.0.0.zip"]);
+    }
+
+    #[tokio::test]
+    async fn resumes_interrupted_downloads() {
+        let root = stub_portal::fixture_root();
+        let zip = std::fs::read(root.join("download/lib/1.0.0.zip")).unwrap();
+        let stub = stub_portal::serve_flaky(root, zip.len() / 2).await;
+        let portal = Portal::new(vec![Mirror::new(&stub.url)]);
+        let credentials = credentials();
+        let lib = portal.fetch_mod_details("lib").await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+
+        // Cut off halfway, then resumed with a Range request
+        portal
+            .download_mod(&lib.releases[0], &credentials, mods_dir)
+            .await
+            .unwrap();
+        assert_eq!(stub.requests(), 3);
+        assert_eq!(std::fs::read(dir.path().join("lib_1.0.0.zip")).unwrap(), zip);
+        assert!(!dir.path().join("lib_1.0.0.zip.part").exists());
+
+        // A partial file left by an earlier run is continued
+        std::fs::remove_file(dir.path().join("lib_1.0.0.zip")).unwrap();
+        std::fs::write(dir.path().join("lib_1.0.0.zip.part"), &zip[..10]).unwrap();
+        portal
+            .download_mod(&lib.releases[0], &credentials, mods_dir)
+            .await
+            .unwrap();
+        assert_eq!(stub.requests(), 4);
+        assert_eq!(std::fs::read(dir.path().join("lib_1.0.0.zip")).unwrap(), zip);
+
+        // A partial file with the wrong bytes is thrown away and fetched again
+        std::fs::remove_file(dir.path().join("lib_1.0.0.zip")).unwrap();
+        std::fs::write(dir.path().join("lib_1.0.0.zip.part"), b"garbage").unwrap();
+        portal
+            .download_mod(&lib.releases[0], &credentials, mods_dir)
+            .await
+            .unwrap();
+        assert_eq!(stub.requests(), 6);
+        assert_eq!(std::fs::read(dir.path().join("lib_1.0.0.zip")).unwrap(), zip);
+    }
 }
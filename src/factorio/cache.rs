@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long the mod list is used without asking the portal.
+pub const MOD_LIST_TTL: Duration = Duration::from_secs(60 * 60);
+/// How long a mod's details are used without asking the portal.
+pub const DETAILS_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// On-disk cache of mod portal responses, one JSON file per response.
+///
+/// Entries past their TTL are revalidated with the ETag they were stored
+/// with, so an unchanged mod list costs a 304 instead of the full download.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// A cached response body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch of the last fetch or revalidation.
+    pub fetched_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    pub body: String,
+}
+
+impl Entry {
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        now().saturating_sub(self.fetched_at) < ttl.as_secs()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Cache { dir }
+    }
+
+    /// `$XDG_CACHE_HOME/fml`, else `~/.cache/fml`.
+    pub fn default_dir() -> Option<PathBuf> {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+            .map(|dir| dir.join("fml"))
+    }
+
+    /// `key` is a relative path such as `mods/flib`; path separators inside
+    /// its components must already have been replaced.
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The stored entry for `key`, however old. Unreadable entries count as
+    /// missing.
+    pub fn get(&self, key: &str) -> Option<Entry> {
+        let contents = std::fs::read_to_string(self.path(key)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Store `body` under `key`, replacing the previous entry atomically.
+    pub fn put(&self, key: &str, etag: Option<String>, body: String) -> Result<Entry> {
+        let entry = Entry {
+            fetched_at: now(),
+            etag,
+            body,
+        };
+        let path = self.path(key);
+        let parent = path.parent().unwrap_or(&self.dir);
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create cache directory {}", parent.display()))?;
+
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string(&entry)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(entry)
+    }
+
+    /// Mark `entry` as just revalidated.
+    pub fn touch(&self, key: &str, entry: Entry) -> Result<Entry> {
+        self.put(key, entry.etag, entry.body)
+    }
+}
+
+/// Cache key of the mod list for one Factorio version.
+pub fn mod_list_key(factorio_version: &str) -> String {
+    format!("mod-list-{}", factorio_version)
+}
+
+/// Cache key of a mod's full details.
+pub fn details_key(name: &str) -> String {
+    format!("mods/{}", name.replace(['/', '\\'], "_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_expires_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("fml"));
+        assert!(cache.get(&details_key("flib")).is_none());
+
+        let entry = cache
+            .put(&details_key("flib"), Some("\"abc\"".to_string()), "{}".to_string())
+            .unwrap();
+        assert!(entry.is_fresh(DETAILS_TTL));
+        assert!(dir.path().join("fml/mods/flib.json").is_file());
+
+        let mut stale = cache.get(&details_key("flib")).unwrap();
+        assert_eq!(stale.etag.as_deref(), Some("\"abc\""));
+        stale.fetched_at -= DETAILS_TTL.as_secs();
+        assert!(!stale.is_fresh(DETAILS_TTL));
+
+        let touched = cache.touch(&details_key("flib"), stale).unwrap();
+        assert!(touched.is_fresh(DETAILS_TTL));
+        assert_eq!(touched.body, "{}");
+    }
+
+    #[test]
+    fn keys_stay_inside_the_cache() {
+        assert_eq!(details_key("../x"), "mods/.._x");
+        assert_eq!(mod_list_key("2.0"), "mod-list-2.0");
+    }
+}
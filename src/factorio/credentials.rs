@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub const USERNAME_ENV: &str = "FML_USERNAME";
+pub const TOKEN_ENV: &str = "FML_TOKEN";
+
+/// Shown when no source has credentials.
+pub const MISSING_HINT: &str = "No Factorio credentials found. Set FML_USERNAME and FML_TOKEN, \
+     fill in username/token in server-settings.json, point secrets_path in fml.json at a file with \
+     username/token, or copy a logged-in client's player-data.json next to the mods directory";
+
+/// Factorio service credentials, needed to download mods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub token: String,
+}
+
+/// Where a set of credentials was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Environment,
+    SecretsFile(PathBuf),
+    ServerSettings(PathBuf),
+    PlayerData(PathBuf),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Environment => write!(f, "{}/{}", USERNAME_ENV, TOKEN_ENV),
+            Source::SecretsFile(p) | Source::ServerSettings(p) | Source::PlayerData(p) => {
+                write!(f, "{}", p.display())
+            }
+        }
+    }
+}
+
+/// The places to look for credentials, tried in field order. Sources that
+/// are missing or only have one of username and token are skipped.
+#[derive(Debug, Default)]
+pub struct Lookup {
+    pub env_username: Option<String>,
+    pub env_token: Option<String>,
+    /// JSON file with `username` and `token`.
+    pub secrets_file: Option<PathBuf>,
+    pub server_settings: Option<PathBuf>,
+    /// Candidate `player-data.json` files of a logged-in game client.
+    pub player_data: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsernameToken {
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerData {
+    #[serde(default, rename = "service-username")]
+    username: String,
+    #[serde(default, rename = "service-token")]
+    token: String,
+}
+
+fn complete(username: String, token: String) -> Option<Credentials> {
+    (!username.is_empty() && !token.is_empty()).then_some(Credentials { username, token })
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Option<T>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let parsed = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(parsed))
+}
+
+impl Lookup {
+    /// The first complete set of credentials, or `None` when there is none
+    /// anywhere (which still allows read-only browsing).
+    pub fn find(&self) -> Result<Option<(Credentials, Source)>> {
+        if let (Some(username), Some(token)) = (&self.env_username, &self.env_token) {
+            if let Some(c) = complete(username.clone(), token.clone()) {
+                return Ok(Some((c, Source::Environment)));
+            }
+        }
+
+        if let Some(path) = &self.secrets_file {
+            let secrets: UsernameToken = read_json(path)?
+                .with_context(|| format!("Secrets file not found: {}", path.display()))?;
+            if let Some(c) = complete(secrets.username, secrets.token) {
+                return Ok(Some((c, Source::SecretsFile(path.clone()))));
+            }
+        }
+
+        if let Some(path) = &self.server_settings {
+            if let Some(settings) = read_json::<UsernameToken>(path)? {
+                if let Some(c) = complete(settings.username, settings.token) {
+                    return Ok(Some((c, Source::ServerSettings(path.clone()))));
+                }
+            }
+        }
+
+        for path in &self.player_data {
+            if let Some(data) = read_json::<PlayerData>(path)? {
+                if let Some(c) = complete(data.username, data.token) {
+                    return Ok(Some((c, Source::PlayerData(path.clone()))));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, json: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+
+    #[test]
+    fn tries_sources_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = write(
+            dir.path(),
+            "server-settings.json",
+            r#"{"name": "srv", "username": "", "token": ""}"#,
+        );
+        let player = write(
+            dir.path(),
+            "player-data.json",
+            r#"{"service-username": "alice", "service-token": "abc"}"#,
+        );
+
+        let mut lookup = Lookup {
+            server_settings: Some(server.clone()),
+            player_data: vec![dir.path().join("missing.json"), player.clone()],
+            ..Default::default()
+        };
+        let (creds, source) = lookup.find().unwrap().unwrap();
+        assert_eq!(creds.username, "alice");
+        assert_eq!(source, Source::PlayerData(player));
+
+        write(
+            dir.path(),
+            "server-settings.json",
+            r#"{"username": "bob", "token": "def"}"#,
+        );
+        let (creds, source) = lookup.find().unwrap().unwrap();
+        assert_eq!(creds.username, "bob");
+        assert_eq!(source, Source::ServerSettings(server));
+
+        lookup.env_username = Some("carol".to_string());
+        lookup.env_token = Some("ghi".to_string());
+        let (creds, source) = lookup.find().unwrap().unwrap();
+        assert_eq!(creds.token, "ghi");
+        assert_eq!(source, Source::Environment);
+    }
+
+    #[test]
+    fn secrets_file_wins_over_server_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = write(dir.path(), "secrets.json", r#"{"username": "s", "token": "t"}"#);
+        let server = write(
+            dir.path(),
+            "server-settings.json",
+            r#"{"username": "bob", "token": "def"}"#,
+        );
+        let lookup = Lookup {
+            // A username without a token is ignored
+            env_username: Some("carol".to_string()),
+            secrets_file: Some(secrets.clone()),
+            server_settings: Some(server),
+            ..Default::default()
+        };
+        let (creds, source) = lookup.find().unwrap().unwrap();
+        assert_eq!(creds.username, "s");
+        assert_eq!(source, Source::SecretsFile(secrets));
+    }
+
+    #[test]
+    fn no_credentials_is_not_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = write(dir.path(), "server-settings.json", r#"{"name": "srv"}"#);
+        let lookup = Lookup {
+            server_settings: Some(server),
+            ..Default::default()
+        };
+        assert!(lookup.find().unwrap().is_none());
+
+        let missing_secrets = Lookup {
+            secrets_file: Some(dir.path().join("nope.json")),
+            ..Default::default()
+        };
+        assert!(missing_secrets.find().is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::installed::InstalledMod;
+use super::mod_list::ModList;
+use super::types::{self, DependencyType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    /// Factorio will refuse to start with this mod set.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "✘ error: {}", self.message),
+            Severity::Warning => write!(f, "! warning: {}", self.message),
+        }
+    }
+}
+
+/// Check the installed mods for problems that stop Factorio from loading
+/// them (errors) or that are merely suspicious (warnings).
+pub fn check(installed: &[InstalledMod], mod_list: &ModList, factorio_version: &str) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut push = |severity: Severity, message: String| issues.push(Issue { severity, message });
+
+    // Factorio loads the newest zip when several versions are present
+    let mut newest: HashMap<&str, &InstalledMod> = HashMap::new();
+    let mut versions: HashMap<&str, Vec<&semver::Version>> = HashMap::new();
+    for m in installed {
+        versions.entry(&m.name).or_default().push(&m.version);
+        let entry = newest.entry(&m.name).or_insert(m);
+        if m.version > entry.version {
+            *entry = m;
+        }
+    }
+
+    let mut names: Vec<&str> = newest.keys().copied().collect();
+    names.sort();
+
+    for name in &names {
+        let mut found = versions[name].clone();
+        if found.len() > 1 {
+            found.sort();
+            let found: Vec<String> = found.iter().map(|v| v.to_string()).collect();
+            push(
+                Severity::Warning,
+                format!(
+                    "{} is installed in several versions ({}); only the newest is loaded",
+                    name,
+                    found.join(", ")
+                ),
+            );
+        }
+    }
+
+    for name in &names {
+        let m = newest[name];
+        // Zips mod-list.json does not mention are loaded too
+        let enabled = mod_list.loads(&m.name);
+
+        if !m.factorio_version.is_empty() && m.factorio_version != factorio_version {
+            let severity = if enabled { Severity::Error } else { Severity::Warning };
+            push(
+                severity,
+                format!(
+                    "{} {} is built for Factorio {}, the server runs {}",
+                    m.name, m.version, m.factorio_version, factorio_version
+                ),
+            );
+        }
+
+        if !enabled {
+            continue;
+        }
+
+        for dep in &m.dependencies {
+            if types::is_builtin_mod(&dep.name) {
+                continue;
+            }
+            let target = newest.get(dep.name.as_str());
+            let target_enabled = target.is_some() && mod_list.loads(&dep.name);
+
+            match dep.dep_type {
+                DependencyType::Required => match target {
+                    None => push(
+                        Severity::Error,
+                        format!("{} requires {}, which is not installed", m.name, dep.name),
+                    ),
+                    Some(_) if !target_enabled => push(
+                        Severity::Error,
+                        format!("{} requires {}, which is disabled", m.name, dep.name),
+                    ),
+                    Some(t) if !dep.version_req.matches(&t.version) => push(
+                        Severity::Error,
+                        format!(
+                            "{} requires {} {}, but {} is installed",
+                            m.name, dep.name, dep.version_req, t.version
+                        ),
+                    ),
+                    Some(_) => {}
+                },
+                // Factorio still enforces the version of an optional dependency that is loaded
+                DependencyType::Optional => {
+                    if let Some(t) = target {
+                        if target_enabled && !dep.version_req.matches(&t.version) {
+                            push(
+                                Severity::Error,
+                                format!(
+                                    "{} optionally depends on {} {}, but {} is enabled",
+                                    m.name, dep.name, dep.version_req, t.version
+                                ),
+                            );
+                        }
+                    }
+                }
+                DependencyType::Incompatible => {
+                    if target_enabled {
+                        push(
+                            Severity::Error,
+                            format!("{} is incompatible with enabled mod {}", m.name, dep.name),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    let mut listed: Vec<_> = mod_list
+        .mods
+        .values()
+        .filter(|e| !types::is_builtin_mod(&e.name) && !newest.contains_key(e.name.as_str()))
+        .collect();
+    listed.sort_by(|a, b| a.name.cmp(&b.name));
+    for entry in listed {
+        let severity = if entry.enabled {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        push(
+            severity,
+            format!("mod-list.json lists {}, but no zip is installed", entry.name),
+        );
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::test_support::make_installed;
+
+    fn enabled(names: &[&str]) -> ModList {
+        let mut list = ModList::new();
+        for name in names {
+            list.set_enabled(name, true);
+        }
+        list
+    }
+
+    fn errors(issues: &[Issue]) -> Vec<&str> {
+        issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| i.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn consistent_set_has_no_issues() {
+        let mods = vec![
+            make_installed("lib", "1.2.0", &[]),
+            make_installed("mod-a", "1.0.0", &["base >= 1.1.0", "lib >= 1.0.0", "? other"]),
+        ];
+        let list = enabled(&["lib", "mod-a"]);
+        assert!(check(&mods, &list, "1.1").is_empty());
+    }
+
+    #[test]
+    fn missing_disabled_and_outdated_dependencies() {
+        let mods = vec![
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("off", "1.0.0", &[]),
+            make_installed("mod-a", "1.0.0", &["lib >= 2.0.0", "gone", "off"]),
+        ];
+        let mut list = enabled(&["lib", "mod-a"]);
+        list.set_enabled("off", false);
+
+        let issues = check(&mods, &list, "1.1");
+        assert_eq!(
+            errors(&issues),
+            vec![
+                "mod-a requires lib >=2.0.0, but 1.0.0 is installed",
+                "mod-a requires gone, which is not installed",
+                "mod-a requires off, which is disabled",
+            ]
+        );
+    }
+
+    #[test]
+    fn enabled_incompatibility() {
+        let mods = vec![
+            make_installed("mod-a", "1.0.0", &["! mod-b"]),
+            make_installed("mod-b", "1.0.0", &[]),
+        ];
+        let issues = check(&mods, &enabled(&["mod-a", "mod-b"]), "1.1");
+        assert_eq!(errors(&issues), vec!["mod-a is incompatible with enabled mod mod-b"]);
+
+        let mut list = enabled(&["mod-a"]);
+        list.set_enabled("mod-b", false);
+        assert!(check(&mods, &list, "1.1").is_empty());
+    }
+
+    #[test]
+    fn unlisted_mods_are_checked_as_enabled() {
+        let mods = vec![
+            make_installed("mod-a", "1.0.0", &["gone", "! mod-b"]),
+            make_installed("mod-b", "1.0.0", &[]),
+        ];
+        let issues = check(&mods, &ModList::new(), "1.1");
+        assert_eq!(
+            errors(&issues),
+            vec![
+                "mod-a requires gone, which is not installed",
+                "mod-a is incompatible with enabled mod mod-b",
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_factorio_version_duplicates_and_missing_zips() {
+        let mods = vec![
+            make_installed("mod-a", "1.0.0", &[]),
+            make_installed("mod-a", "1.1.0", &[]),
+        ];
+        let mut list = enabled(&["mod-a", "ghost"]);
+        list.set_enabled("space-age", true);
+
+        let issues = check(&mods, &list, "2.0");
+        assert_eq!(
+            errors(&issues),
+            vec![
+                "mod-a 1.1.0 is built for Factorio 1.1, the server runs 2.0",
+                "mod-list.json lists ghost, but no zip is installed",
+            ]
+        );
+        assert!(issues
+            .iter()
+            .any(|i| i.severity == Severity::Warning && i.message.contains("several versions")));
+    }
+}
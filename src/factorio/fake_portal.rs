@@ -0,0 +1,297 @@
+//! Mod portals that never touch the network, for testing whole install,
+//! update and TUI flows.
+//!
+//! [`FixturePortal`] reads the same directory layout the stub HTTP server in
+//! `stub_portal` serves; [`MemoryPortal`] is built up in code.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::api::{self, Progress};
+use super::credentials::Credentials;
+use super::portal::{BoxFuture, ModPortal, NotFound, Reporter, SharedPortal};
+use super::test_support;
+use super::types::{Mod, ModListEntry, Release};
+
+/// Serves `<root>/api/mods.json`, `<root>/api/mods/<name>/full.json` and
+/// release zips at `<root><download_url>.zip`. Any credentials are accepted.
+#[derive(Clone)]
+pub struct FixturePortal {
+    root: PathBuf,
+    progress: Option<Reporter>,
+}
+
+impl FixturePortal {
+    pub fn new(root: PathBuf) -> Self {
+        FixturePortal {
+            root,
+            progress: None,
+        }
+    }
+
+    fn read(&self, path: &str) -> Option<String> {
+        std::fs::read_to_string(self.root.join(path)).ok()
+    }
+}
+
+impl ModPortal for FixturePortal {
+    fn fetch_mod_list<'a>(
+        &'a self,
+        _factorio_version: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ModListEntry>>> {
+        Box::pin(async move {
+            let body = self
+                .read("api/mods.json")
+                .context("No mod list in the fixture")?;
+            api::parse_mod_list(&body)
+        })
+    }
+
+    fn fetch_mod_details<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Mod>> {
+        Box::pin(async move {
+            let Some(body) = self.read(&format!("api/mods/{}/full.json", name)) else {
+                return Err(NotFound(name.to_string()).into());
+            };
+            api::parse_mod_details(&body)
+        })
+    }
+
+    fn fetch_mods_by_name<'a>(&'a self, names: &'a [String]) -> BoxFuture<'a, Result<Vec<Mod>>> {
+        Box::pin(async move {
+            let mut mods = Vec::new();
+            for name in names {
+                if let Ok(m) = self.fetch_mod_details(name).await {
+                    mods.push(m);
+                }
+            }
+            Ok(mods)
+        })
+    }
+
+    fn download_mod<'a>(
+        &'a self,
+        release: &'a Release,
+        _credentials: &'a Credentials,
+        dir: &'a str,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = format!("{}.zip", release.download_url.trim_start_matches('/'));
+            let body = std::fs::read(self.root.join(&path))
+                .with_context(|| format!("Download failed for '{}'", release.file_name))?;
+            place(release, &body, dir, self.progress.as_ref())
+        })
+    }
+
+    fn validate_credentials<'a>(
+        &'a self,
+        _credentials: &'a Credentials,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async { Ok(true) })
+    }
+
+    fn with_reporter(&self, report: Reporter) -> SharedPortal {
+        let mut portal = self.clone();
+        portal.progress = Some(report);
+        Arc::new(portal)
+    }
+}
+
+/// A portal held in memory. Releases added with
+/// [`MemoryPortal::with_release`] come with a real zip to download.
+#[derive(Clone, Default)]
+pub struct MemoryPortal {
+    mods: BTreeMap<String, Mod>,
+    /// Zips by download URL.
+    zips: HashMap<String, Vec<u8>>,
+    /// The only credentials accepted; any when `None`.
+    credentials: Option<Credentials>,
+    progress: Option<Reporter>,
+    /// Mods whose details cannot be fetched, as if the portal were down.
+    unreachable: HashSet<String>,
+    /// Details requests running now, and the most that ever ran at once.
+    fetching: Arc<AtomicUsize>,
+    most_fetching: Arc<AtomicUsize>,
+}
+
+impl MemoryPortal {
+    pub fn new() -> Self {
+        MemoryPortal::default()
+    }
+
+    /// Serve `m` as given. Its releases have nothing to download.
+    pub fn with_mod(mut self, m: Mod) -> Self {
+        self.mods.insert(m.name.clone(), m);
+        self
+    }
+
+    /// Add a Factorio 1.1 release of `name` with a generated zip.
+    pub fn with_release(mut self, name: &str, version: &str, dependencies: &[&str]) -> Self {
+        let zip = test_support::mod_zip_bytes(name, version, dependencies);
+        let release = Release {
+            download_url: format!("/download/{}/{}", name, version),
+            file_name: format!("{}_{}.zip", name, version),
+            version: semver::Version::parse(version).unwrap(),
+            factorio_version: "1.1".to_string(),
+            sha1: sha1_smol::Sha1::from(&zip).digest().to_string(),
+            dependencies: dependencies.iter().map(|d| d.parse().unwrap()).collect(),
+        };
+        self.zips.insert(release.download_url.clone(), zip);
+
+        let m = self.mods.entry(name.to_string()).or_insert_with(|| Mod {
+            name: name.to_string(),
+            releases: Vec::new(),
+        });
+        m.releases.push(release);
+        m.releases.sort_by(|a, b| a.version.cmp(&b.version));
+        self
+    }
+
+    /// Fail every details request for `name` as if the portal were down.
+    pub fn with_unreachable(mut self, name: &str) -> Self {
+        self.unreachable.insert(name.to_string());
+        self
+    }
+
+    /// The most details requests that were running at the same time.
+    pub fn most_concurrent_fetches(&self) -> usize {
+        self.most_fetching.load(Ordering::SeqCst)
+    }
+
+    /// Reject every credentials but `credentials`.
+    pub fn accepting(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+}
+
+impl ModPortal for MemoryPortal {
+    fn fetch_mod_list<'a>(
+        &'a self,
+        factorio_version: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ModListEntry>>> {
+        Box::pin(async move {
+            let mut entries: Vec<ModListEntry> = self
+                .mods
+                .values()
+                .filter(|m| {
+                    m.releases
+                        .iter()
+                        .any(|r| r.factorio_version == factorio_version)
+                })
+                .map(|m| ModListEntry {
+                    name: m.name.clone(),
+                    title: m.name.clone(),
+                    downloads_count: 0,
+                })
+                .collect();
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(entries)
+        })
+    }
+
+    fn fetch_mod_details<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Mod>> {
+        Box::pin(async move {
+            let now = self.fetching.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_fetching.fetch_max(now, Ordering::SeqCst);
+            // Let other requests start before this one answers
+            tokio::task::yield_now().await;
+            self.fetching.fetch_sub(1, Ordering::SeqCst);
+
+            if self.unreachable.contains(name) {
+                bail!(
+                    "Failed to fetch details for mod '{}': connection refused",
+                    name
+                );
+            }
+            match self.mods.get(name) {
+                Some(m) => Ok(m.clone()),
+                None => Err(NotFound(name.to_string()).into()),
+            }
+        })
+    }
+
+    fn fetch_mods_by_name<'a>(&'a self, names: &'a [String]) -> BoxFuture<'a, Result<Vec<Mod>>> {
+        Box::pin(async move {
+            Ok(names
+                .iter()
+                .filter_map(|name| self.mods.get(name))
+                .map(|m| {
+                    let mut m = m.clone();
+                    for release in &mut m.releases {
+                        release.dependencies.clear();
+                    }
+                    m
+                })
+                .collect())
+        })
+    }
+
+    fn download_mod<'a>(
+        &'a self,
+        release: &'a Release,
+        credentials: &'a Credentials,
+        dir: &'a str,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if self.credentials.as_ref().is_some_and(|c| c != credentials) {
+                bail!(
+                    "Download failed for '{}' (HTTP 403 Forbidden): check your Factorio username and token",
+                    release.file_name
+                );
+            }
+            let Some(body) = self.zips.get(&release.download_url) else {
+                bail!(
+                    "Download failed for '{}' (HTTP 404 Not Found)",
+                    release.file_name
+                );
+            };
+            place(release, body, dir, self.progress.as_ref())
+        })
+    }
+
+    fn validate_credentials<'a>(
+        &'a self,
+        credentials: &'a Credentials,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move { Ok(self.credentials.as_ref().is_none_or(|c| c == credentials)) })
+    }
+
+    fn with_reporter(&self, report: Reporter) -> SharedPortal {
+        let mut portal = self.clone();
+        portal.progress = Some(report);
+        Arc::new(portal)
+    }
+}
+
+/// Write `body` to `<dir>/<file_name>` the way a real download ends up:
+/// verified through a `.part` file, with progress at the start and the end.
+fn place(release: &Release, body: &[u8], dir: &str, progress: Option<&Reporter>) -> Result<()> {
+    let report = |downloaded: usize| {
+        if let Some(progress) = progress {
+            progress(Progress {
+                file_name: release.file_name.clone(),
+                downloaded: downloaded as u64,
+                total: Some(body.len() as u64),
+            });
+        }
+    };
+    report(0);
+
+    let file_path = Path::new(dir).join(&release.file_name);
+    let part_path = Path::new(dir).join(format!("{}.part", release.file_name));
+    std::fs::write(&part_path, body)
+        .with_context(|| format!("Failed to write file: {}", part_path.display()))?;
+    let digest = sha1_smol::Sha1::from(body).digest().to_string();
+    if let Err(e) = api::verify(release, &part_path, &digest) {
+        let _ = std::fs::remove_file(&part_path);
+        return Err(e);
+    }
+    std::fs::rename(&part_path, &file_path)
+        .with_context(|| format!("Failed to move download to {}", file_path.display()))?;
+
+    report(body.len());
+    Ok(())
+}
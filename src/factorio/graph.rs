@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use super::installed::InstalledMod;
+use super::types::{self, Dependency, DependencyType};
+
+/// Newest installed version per mod name, as Factorio would load it.
+fn newest_by_name(installed: &[InstalledMod]) -> HashMap<&str, &InstalledMod> {
+    let mut by_name: HashMap<&str, &InstalledMod> = HashMap::new();
+    for m in installed {
+        let entry = by_name.entry(&m.name).or_insert(m);
+        if m.version > entry.version {
+            *entry = m;
+        }
+    }
+    by_name
+}
+
+fn requires(m: &InstalledMod, name: &str) -> bool {
+    m.dependencies
+        .iter()
+        .any(|d| d.dep_type == DependencyType::Required && d.name == name)
+}
+
+/// Render the dependency tree of `root`, or of every installed mod that no
+/// other installed mod requires.
+///
+/// Edges are prefixed like in info.json (`?` optional, `!` incompatible,
+/// `~` required without load order). Only required edges are expanded, and a
+/// mod already expanded elsewhere is marked `(*)`.
+pub fn tree(installed: &[InstalledMod], root: Option<&str>) -> Result<String> {
+    let by_name = newest_by_name(installed);
+
+    let mut roots: Vec<&InstalledMod> = match root {
+        Some(name) => vec![by_name
+            .get(name)
+            .copied()
+            .with_context(|| format!("Mod '{}' is not installed", name))?],
+        None => by_name
+            .values()
+            .filter(|m| !by_name.values().any(|other| requires(other, &m.name)))
+            .copied()
+            .collect(),
+    };
+    // Pure cycles have no top-level mod; fall back to listing everything
+    if roots.is_empty() {
+        roots = by_name.values().copied().collect();
+    }
+    roots.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut out = String::new();
+    let mut expanded: HashSet<&str> = HashSet::new();
+    for m in roots {
+        if !expanded.insert(&m.name) {
+            continue;
+        }
+        let _ = writeln!(out, "{} {}", m.name, m.version);
+        render_children(m, "", &by_name, &mut expanded, &mut out);
+    }
+    Ok(out)
+}
+
+fn render_children<'a>(
+    m: &'a InstalledMod,
+    prefix: &str,
+    by_name: &HashMap<&'a str, &'a InstalledMod>,
+    expanded: &mut HashSet<&'a str>,
+    out: &mut String,
+) {
+    let deps: Vec<&Dependency> = m.dependencies.iter().filter(|d| d.name != "base").collect();
+
+    for (i, dep) in deps.iter().enumerate() {
+        let last = i == deps.len() - 1;
+        let marker = match dep.dep_type {
+            DependencyType::Required if !dep.affects_load_order => "~ ",
+            DependencyType::Required => "",
+            DependencyType::Optional => "? ",
+            DependencyType::Incompatible => "! ",
+        };
+        let req = if dep.version_req == semver::VersionReq::STAR {
+            String::new()
+        } else {
+            format!(" {}", dep.version_req)
+        };
+        let target = by_name.get(dep.name.as_str()).copied();
+        let state = match target {
+            Some(t) => format!("({})", t.version),
+            None if types::is_builtin_mod(&dep.name) => "(built in)".to_string(),
+            None => "(not installed)".to_string(),
+        };
+
+        let expand = target.filter(|_| dep.dep_type == DependencyType::Required);
+        let repeated = expand.is_some_and(|t| !expanded.insert(&t.name));
+
+        let _ = writeln!(
+            out,
+            "{}{}{}{}{} {}{}",
+            prefix,
+            if last { "└── " } else { "├── " },
+            marker,
+            dep.name,
+            req,
+            state,
+            if repeated { " (*)" } else { "" }
+        );
+
+        if let Some(t) = expand {
+            if !repeated {
+                let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                render_children(t, &child_prefix, by_name, expanded, out);
+            }
+        }
+    }
+}
+
+/// Every chain of installed mods that (transitively) requires `target`,
+/// each ordered from the top-level mod down to `target`.
+pub fn why(installed: &[InstalledMod], target: &str) -> Vec<Vec<String>> {
+    let by_name = newest_by_name(installed);
+    let mut chains = Vec::new();
+    let mut path = vec![target.to_string()];
+    collect_chains(&by_name, &mut path, &mut chains);
+    chains.sort();
+    chains
+}
+
+fn collect_chains(
+    by_name: &HashMap<&str, &InstalledMod>,
+    path: &mut Vec<String>,
+    chains: &mut Vec<Vec<String>>,
+) {
+    let current = &path[path.len() - 1];
+    let mut dependents: Vec<&str> = by_name
+        .values()
+        .filter(|m| requires(m, current) && !path.contains(&m.name))
+        .map(|m| m.name.as_str())
+        .collect();
+    dependents.sort();
+
+    if dependents.is_empty() {
+        if path.len() > 1 {
+            chains.push(path.iter().rev().cloned().collect());
+        }
+        return;
+    }
+
+    for dependent in dependents {
+        path.push(dependent.to_string());
+        collect_chains(by_name, path, chains);
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::test_support::make_installed;
+
+    fn sample() -> Vec<InstalledMod> {
+        vec![
+            make_installed(
+                "mod-a",
+                "1.0.0",
+                &["base >= 1.1.0", "lib >= 1.0.0", "~ mid", "? extra", "! bad"],
+            ),
+            make_installed("mid", "2.0.0", &["lib"]),
+            make_installed("lib", "1.2.0", &["space-age"]),
+        ]
+    }
+
+    #[test]
+    fn renders_tree_from_top_level_mods() {
+        let out = tree(&sample(), None).unwrap();
+        let expected = "\
+mod-a 1.0.0
+├── lib >=1.0.0 (1.2.0)
+│   └── space-age (built in)
+├── ~ mid (2.0.0)
+│   └── lib (1.2.0) (*)
+├── ? extra (not installed)
+└── ! bad (not installed)
+";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn renders_tree_for_single_mod() {
+        let out = tree(&sample(), Some("mid")).unwrap();
+        assert_eq!(out, "mid 2.0.0\n└── lib (1.2.0)\n    └── space-age (built in)\n");
+        assert!(tree(&sample(), Some("nope")).is_err());
+    }
+
+    #[test]
+    fn why_lists_every_chain() {
+        let chains = why(&sample(), "lib");
+        assert_eq!(
+            chains,
+            vec![
+                vec!["mod-a".to_string(), "lib".to_string()],
+                vec!["mod-a".to_string(), "mid".to_string(), "lib".to_string()],
+            ]
+        );
+        assert!(why(&sample(), "mod-a").is_empty());
+    }
+
+    #[test]
+    fn why_survives_cycles() {
+        let mods = vec![
+            make_installed("a", "1.0.0", &["b"]),
+            make_installed("b", "1.0.0", &["a"]),
+        ];
+        assert_eq!(why(&mods, "a"), vec![vec!["b".to_string(), "a".to_string()]]);
+    }
+}
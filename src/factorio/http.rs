@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Sent with every request so portal operators can tell where traffic comes from.
+pub const USER_AGENT: &str = concat!(
+    "fml/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/Max-Leopold/fml)"
+);
+
+/// Wait before the first retry; doubled for every further one. Tests only
+/// ever talk to local stubs and closed ports, so they need not wait long.
+const BASE_DELAY: Duration = if cfg!(test) {
+    Duration::from_millis(5)
+} else {
+    Duration::from_millis(500)
+};
+
+/// Longest wait between two attempts, whatever the server asks for.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Connection settings for every request to the mod portal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpSettings {
+    /// Seconds to wait for a connection.
+    pub connect_timeout: u64,
+    /// Seconds to wait for the next bytes of a response.
+    pub read_timeout: u64,
+    /// Further attempts after a timeout, a failed connection, HTTP 429 or a
+    /// 5xx answer.
+    pub retries: u32,
+    /// Proxy for all requests, e.g. `http://proxy:3128`. Without one, the
+    /// `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` variables apply.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            connect_timeout: 10,
+            read_timeout: 30,
+            retries: 3,
+            proxy: None,
+        }
+    }
+}
+
+impl HttpSettings {
+    pub fn is_default(&self) -> bool {
+        *self == HttpSettings::default()
+    }
+}
+
+/// One connection pool shared by all requests, retrying transient failures.
+///
+/// Timeouts, failed connections, HTTP 429 and 500/502/503/504 answers are retried with
+/// exponential backoff, or after the `Retry-After` delay the server asked
+/// for. Anything else is returned as is.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    retries: u32,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClient::new(&HttpSettings::default()).expect("Failed to set up the HTTP client")
+    }
+}
+
+impl HttpClient {
+    pub fn new(settings: &HttpSettings) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(Duration::from_secs(settings.connect_timeout))
+            .read_timeout(Duration::from_secs(settings.read_timeout));
+        if let Some(proxy) = &settings.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .with_context(|| format!("Invalid proxy URL: {}", proxy))?;
+            builder = builder.proxy(proxy);
+        }
+        Ok(HttpClient {
+            client: builder.build().context("Failed to set up the HTTP client")?,
+            retries: settings.retries,
+        })
+    }
+
+    /// GET `url`. After the last retry, the final answer or error is returned.
+    pub async fn get(&self, url: Url, headers: HeaderMap) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .client
+                .get(url.clone())
+                .headers(headers.clone())
+                .send()
+                .await;
+            let retry_after = match &result {
+                Ok(resp) if is_transient(resp.status()) => Some(retry_after(resp.headers())),
+                Err(e) if e.is_timeout() || e.is_connect() => Some(None),
+                _ => None,
+            };
+            match retry_after {
+                Some(requested) if attempt < self.retries => {
+                    tokio::time::sleep(retry_delay(attempt, requested)).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+/// Answers that say more about the server's state than about the request.
+pub fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// A `Retry-After` given in seconds; the HTTP-date form falls back to backoff.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// How long to wait before retry number `attempt` (from 0).
+fn retry_delay(attempt: u32, requested: Option<Duration>) -> Duration {
+    requested
+        .unwrap_or_else(|| BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)))
+        .min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially_unless_told_otherwise() {
+        assert_eq!(retry_delay(0, None), BASE_DELAY);
+        assert_eq!(retry_delay(2, None), BASE_DELAY * 4);
+        assert_eq!(retry_delay(30, None), MAX_DELAY);
+        assert_eq!(retry_delay(2, Some(Duration::ZERO)), Duration::ZERO);
+        assert_eq!(retry_delay(0, Some(Duration::from_secs(3600))), MAX_DELAY);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(
+            header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn rejects_invalid_proxies() {
+        let settings = HttpSettings {
+            proxy: Some("not a url".to_string()),
+            ..HttpSettings::default()
+        };
+        let err = HttpClient::new(&settings).unwrap_err().to_string();
+        assert!(err.contains("Invalid proxy URL"), "{}", err);
+        assert!(HttpClient::new(&HttpSettings::default()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_server_is_up() {
+        use crate::factorio::stub_portal;
+
+        // Nothing listens on the port when the first attempts are made
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let settings = HttpSettings {
+            retries: 10,
+            ..HttpSettings::default()
+        };
+        let client = HttpClient::new(&settings).unwrap();
+        let url = Url::parse(&format!("http://{}/api/mods/lib/full", addr)).unwrap();
+        let request = tokio::spawn(async move { client.get(url, HeaderMap::new()).await });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let stub = stub_portal::serve_at(stub_portal::fixture_root(), addr)
+            .await
+            .unwrap();
+        let resp = request.await.unwrap().unwrap();
+        assert!(resp.status().is_success());
+        assert_eq!(stub.requests(), 1);
+
+        let settings = HttpSettings {
+            retries: 0,
+            ..HttpSettings::default()
+        };
+        let url = Url::parse(&stub_portal::dead_url().await).unwrap();
+        let err = HttpClient::new(&settings).unwrap().get(url, HeaderMap::new()).await;
+        assert!(err.unwrap_err().is_connect());
+    }
+}
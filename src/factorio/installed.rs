@@ -1,14 +1,21 @@
 use anyhow::{bail, Context, Result};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::io::Read;
 use std::path::Path;
 
+use super::mod_list::ModList;
+use super::types::{Dependency, DependencyType};
+
 #[derive(Debug, Clone)]
 pub struct InstalledMod {
     pub name: String,
     pub version: semver::Version,
     pub title: String,
     pub factorio_version: String,
+    pub dependencies: Vec<Dependency>,
+    /// Name of the zip in the mods directory.
+    pub file_name: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -19,6 +26,8 @@ struct InfoJson {
     title: String,
     #[serde(default)]
     factorio_version: String,
+    #[serde(default)]
+    dependencies: Vec<String>,
 }
 
 pub fn read_installed_mods(mods_dir: &str) -> Result<Vec<InstalledMod>> {
@@ -57,10 +66,19 @@ pub fn read_installed_mods(mods_dir: &str) -> Result<Vec<InstalledMod>> {
         }
     }
 
+    #[allow(clippy::unnecessary_sort_by)]
     installed.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
     Ok(installed)
 }
 
+/// Installed mod names mapped to their versions, as the resolver expects them.
+pub fn read_installed_versions(mods_dir: &str) -> Result<HashMap<String, semver::Version>> {
+    Ok(read_installed_mods(mods_dir)?
+        .into_iter()
+        .map(|m| (m.name, m.version))
+        .collect())
+}
+
 fn parse_mod_zip(path: &Path) -> Result<InstalledMod> {
     let file = std::fs::File::open(path)
         .with_context(|| format!("Failed to open {}", path.display()))?;
@@ -106,43 +124,64 @@ fn parse_mod_zip(path: &Path) -> Result<InstalledMod> {
         info.title
     };
 
+    let dependencies = info
+        .dependencies
+        .iter()
+        .filter_map(|d| d.parse().ok())
+        .collect();
+
     Ok(InstalledMod {
         name: info.name,
         version,
         title,
         factorio_version: info.factorio_version,
+        dependencies,
+        file_name: path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
     })
 }
 
-pub fn delete_mod(mod_name: &str, version: &semver::Version, mods_dir: &str) -> Result<()> {
-    let dir = Path::new(mods_dir);
-    let expected_prefix = format!("{}_", mod_name);
-
-    for entry in std::fs::read_dir(dir).context("Failed to read mods directory")? {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+/// The installed mods Factorio loads: those mod-list.json enables, and those
+/// it does not list yet.
+pub fn loaded(mods: &[InstalledMod], mod_list: &ModList) -> Vec<InstalledMod> {
+    mods.iter()
+        .filter(|m| mod_list.loads(&m.name))
+        .cloned()
+        .collect()
+}
 
-        let file_name = entry.file_name().to_string_lossy().to_string();
+/// Names of the loaded installed mods that hard-depend on `mod_name`.
+pub fn required_by(mods: &[InstalledMod], mod_list: &ModList, mod_name: &str) -> Vec<String> {
+    mods.iter()
+        .filter(|m| m.name != mod_name && mod_list.loads(&m.name))
+        .filter(|m| {
+            m.dependencies
+                .iter()
+                .any(|d| d.dep_type == DependencyType::Required && d.name == mod_name)
+        })
+        .map(|m| m.name.clone())
+        .collect()
+}
 
-        if file_name.starts_with(&expected_prefix) && file_name.ends_with(".zip") {
-            // Verify this is the exact mod by checking it matches name_version.zip
-            let expected_file = format!("{}_{}.zip", mod_name, version);
-            if file_name == expected_file {
-                std::fs::remove_file(entry.path())
-                    .with_context(|| format!("Failed to delete {}", file_name))?;
-                return Ok(());
-            }
+/// SHA-1 hex digest of a file, read in chunks.
+pub fn file_sha1(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if n == 0 {
+            break;
         }
+        hasher.update(&buf[..n]);
     }
-
-    bail!(
-        "Mod file not found: {}_{}.zip in {}",
-        mod_name,
-        version,
-        mods_dir
-    );
+    Ok(hasher.digest().to_string())
 }
 
 #[cfg(test)]
@@ -158,4 +197,22 @@ mod tests {
         assert!("boblogistics_1.0.0.zip".starts_with(&prefix_boblogistics));
         assert!("bob_1.0.0.zip".starts_with(&prefix_bob));
     }
+
+    #[test]
+    fn reads_dependencies_and_file_name() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::factorio::test_support::write_mod_zip(
+            dir.path(),
+            "mod-a",
+            "1.2.3",
+            &["base >= 1.1.0", "? lib"],
+        );
+
+        let mods = super::read_installed_mods(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(mods.len(), 1);
+        assert_eq!(mods[0].name, "mod-a");
+        assert_eq!(mods[0].file_name, "mod-a_1.2.3.zip");
+        assert_eq!(mods[0].dependencies.len(), 2);
+        assert_eq!(mods[0].dependencies[1].name, "lib");
+    }
 }
@@ -0,0 +1,1245 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::credentials::Credentials;
+use super::installed::{self, InstalledMod};
+use super::lockfile::{self, Lockfile};
+use super::manifest::{Action, Plan};
+use super::mod_list::ModList;
+use super::portal::SharedPortal;
+use super::resolver;
+use super::save::SaveMod;
+use super::transaction::Transaction;
+use super::types::{self, DependencyType, Release};
+
+/// What to do when a mod that is about to be removed is still required by
+/// another enabled mod.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoveMode {
+    /// Refuse to remove it.
+    Refuse,
+    /// Remove it anyway and leave the dependents broken.
+    Force,
+    /// Remove the dependents as well.
+    Cascade,
+}
+
+/// Resolve a mod against the portal and download it together with every
+/// dependency that is not installed yet, as part of `tx`.
+///
+/// Returns the downloaded releases in dependency-first order. An empty list
+/// means the mod was already installed in a matching version. Nothing reaches
+/// the mods directory unless every download succeeds.
+pub async fn install(
+    tx: &mut Transaction,
+    portal: &SharedPortal,
+    mod_name: &str,
+    version_req: &semver::VersionReq,
+    factorio_version: &str,
+    credentials: &Credentials,
+    installed_map: &HashMap<String, semver::Version>,
+) -> Result<Vec<(String, Release)>> {
+    // Resolve dependencies, keeping clear of what the loaded mods rule out
+    let mod_list = ModList::load_or_create(tx.mods_dir())?;
+    let loaded = installed::loaded(&installed::read_installed_mods(tx.mods_dir())?, &mod_list);
+    let resolve_result = resolver::resolve(
+        mod_name,
+        version_req,
+        factorio_version,
+        installed_map,
+        &loaded,
+        &**portal,
+    )
+    .await?;
+
+    tx.download(portal, &resolve_result.to_download, credentials)
+        .await?;
+
+    Ok(resolve_result.to_download)
+}
+
+/// Download `releases` into `dir`, up to the portal's concurrency limit at
+/// a time. Installs use a [`Transaction`]'s staging directory here.
+///
+/// When one download fails the others are cancelled and every zip this call
+/// already placed is deleted again, so `dir` is left as it was. Only the
+/// `.part` files of cancelled downloads stay, to be resumed later.
+pub async fn download_all(
+    portal: &SharedPortal,
+    releases: &[(String, Release)],
+    credentials: &Credentials,
+    dir: &str,
+) -> Result<()> {
+    let existed: Vec<bool> = releases
+        .iter()
+        .map(|(_, r)| Path::new(dir).join(&r.file_name).exists())
+        .collect();
+    let mut pending = releases.iter().enumerate();
+    let mut running = tokio::task::JoinSet::new();
+    let mut placed: Vec<usize> = Vec::new();
+    let mut failure: Option<(usize, anyhow::Error)> = None;
+
+    loop {
+        while failure.is_none() && running.len() < portal.concurrency() {
+            let Some((i, (_, release))) = pending.next() else {
+                break;
+            };
+            let portal = portal.clone();
+            let release = release.clone();
+            let credentials = credentials.clone();
+            let dir = dir.to_string();
+            running.spawn(async move {
+                let result = portal.download_mod(&release, &credentials, &dir).await;
+                (i, result)
+            });
+        }
+
+        let Some(joined) = running.join_next().await else {
+            break;
+        };
+        match joined {
+            // Never delete a zip this call did not put there
+            Ok((i, Ok(()))) if existed[i] => {}
+            Ok((i, Ok(()))) => placed.push(i),
+            Ok((i, Err(e))) => {
+                if failure.is_none() {
+                    failure = Some((i, e));
+                    running.abort_all();
+                }
+            }
+            Err(e) if e.is_cancelled() => {}
+            Err(e) => bail!("Download task failed: {}", e),
+        }
+    }
+
+    let Some((failed, e)) = failure else {
+        return Ok(());
+    };
+    for i in &placed {
+        let path = Path::new(dir).join(&releases[*i].1.file_name);
+        let _ = std::fs::remove_file(path);
+    }
+    Err(anyhow::anyhow!(
+        "Failed to download '{}' ({} other download(s) removed again): {}",
+        releases[failed].0,
+        placed.len(),
+        e
+    ))
+}
+
+/// Outcome of [`install_locked`].
+#[derive(Debug, Default)]
+pub struct LockedInstallReport {
+    pub downloaded: Vec<(String, Release)>,
+    pub removed: Vec<InstalledMod>,
+    pub unchanged: usize,
+}
+
+/// Make the mods directory match `lock` exactly: download missing or
+/// differing zips and remove every mod the lockfile does not list.
+///
+/// All locked releases are looked up before anything is downloaded, so a
+/// release that is gone from the portal or whose hash changed aborts the run
+/// without touching the mods directory.
+pub async fn install_locked(
+    portal: &SharedPortal,
+    lock: &Lockfile,
+    credentials: &Credentials,
+    mods_dir: &str,
+) -> Result<LockedInstallReport> {
+    let mods = installed::read_installed_mods(mods_dir)?;
+    let mut report = LockedInstallReport::default();
+    let mut to_download: Vec<(String, Release)> = Vec::new();
+
+    for locked in &lock.mods {
+        let current = mods
+            .iter()
+            .find(|m| m.name == locked.name && m.version == locked.version);
+        if let Some(m) = current {
+            let sha1 = installed::file_sha1(&Path::new(mods_dir).join(&m.file_name))?;
+            if sha1 == locked.sha1 {
+                report.unchanged += 1;
+                continue;
+            }
+        }
+
+        let details = portal.fetch_mod_details(&locked.name).await?;
+        let release = details
+            .releases
+            .into_iter()
+            .find(|r| r.version == locked.version)
+            .with_context(|| {
+                format!(
+                    "Locked release {} {} is no longer available on the mod portal",
+                    locked.name, locked.version
+                )
+            })?;
+        if release.sha1 != locked.sha1 {
+            bail!(
+                "SHA1 of {} {} on the mod portal ({}) does not match fml.lock ({})",
+                locked.name,
+                locked.version,
+                release.sha1,
+                locked.sha1
+            );
+        }
+        to_download.push((locked.name.clone(), release));
+    }
+
+    let mut tx = Transaction::begin(mods_dir)?;
+    let result: Result<()> = async {
+        tx.download(portal, &to_download, credentials).await?;
+        report.downloaded = to_download;
+
+        // Drop everything the lockfile does not list, including replaced versions
+        let mut mod_list = ModList::load_or_create(mods_dir)?;
+        for m in installed::read_installed_mods(mods_dir)? {
+            let is_locked = lock
+                .mods
+                .iter()
+                .any(|l| l.name == m.name && l.version == m.version);
+            if is_locked {
+                continue;
+            }
+            tx.delete_mod(&m)?;
+            if !lock.mods.iter().any(|l| l.name == m.name) {
+                mod_list.remove(&m.name);
+            }
+            report.removed.push(m);
+        }
+        for locked in &lock.mods {
+            if !mod_list.mods.contains_key(&locked.name) {
+                mod_list.set_enabled(&locked.name, true);
+            }
+        }
+        tx.save_mod_list(&mod_list)
+    }
+    .await;
+    tx.finish(result)?;
+
+    Ok(report)
+}
+
+/// Outcome of [`install_save`].
+#[derive(Debug, Default)]
+pub struct SaveInstallReport {
+    pub downloaded: Vec<(String, Release)>,
+    /// Installed versions that differed from the save and were deleted.
+    pub replaced: Vec<InstalledMod>,
+    pub unchanged: usize,
+}
+
+/// Install exactly the mod versions a save was made with and set
+/// mod-list.json so that those mods, and only those, are enabled. The
+/// lockfile at `lock_path` is rewritten in the same transaction.
+///
+/// Every save mod is resolved pinned to its recorded version, with the other
+/// save mods treated as installed, before anything is downloaded.
+pub async fn install_save(
+    portal: &SharedPortal,
+    mods: &[SaveMod],
+    factorio_version: &str,
+    credentials: &Credentials,
+    mods_dir: &str,
+    lock_path: &Path,
+) -> Result<SaveInstallReport> {
+    let installed_mods = installed::read_installed_mods(mods_dir)?;
+    let mut report = SaveInstallReport::default();
+
+    // What the mods directory will hold once the save's versions are in place
+    let mut target: HashMap<String, semver::Version> = installed_mods
+        .iter()
+        .map(|m| (m.name.clone(), m.version.clone()))
+        .collect();
+    for m in mods {
+        target.insert(m.name.clone(), m.version.clone());
+    }
+    // Only the save's mods stay enabled
+    let enabled: Vec<InstalledMod> = installed_mods
+        .iter()
+        .filter(|i| mods.iter().any(|m| m.name == i.name))
+        .cloned()
+        .collect();
+
+    let mut to_download: Vec<(String, Release)> = Vec::new();
+    for m in mods.iter().filter(|m| !types::is_builtin_mod(&m.name)) {
+        let present = installed_mods
+            .iter()
+            .any(|i| i.name == m.name && i.version == m.version);
+        if present {
+            report.unchanged += 1;
+            continue;
+        }
+        if to_download.iter().any(|(n, _)| n == &m.name) {
+            continue;
+        }
+
+        let mut resolve_map = target.clone();
+        resolve_map.remove(&m.name);
+        let exact = semver::VersionReq::parse(&format!("={}", m.version))?;
+        let result = resolver::resolve(
+            &m.name,
+            &exact,
+            factorio_version,
+            &resolve_map,
+            &enabled,
+            &**portal,
+        )
+        .await?;
+        for (name, release) in result.to_download {
+            if !to_download.iter().any(|(n, _)| n == &name) {
+                target.insert(name.clone(), release.version.clone());
+                to_download.push((name, release));
+            }
+        }
+    }
+
+    let mut tx = Transaction::begin(mods_dir)?;
+    let result: Result<()> = async {
+        tx.download(portal, &to_download, credentials).await?;
+        report.downloaded = to_download;
+
+        // Factorio loads the newest zip, so other versions of save mods must go
+        for m in installed_mods {
+            let wanted = mods.iter().find(|s| s.name == m.name);
+            if wanted.is_some_and(|s| s.version != m.version) {
+                tx.delete_mod(&m)?;
+                report.replaced.push(m);
+            }
+        }
+
+        let mut mod_list = ModList::load_or_create(mods_dir)?;
+        let listed: Vec<String> = mod_list.mods.keys().cloned().collect();
+        for name in listed {
+            mod_list.set_enabled(&name, false);
+        }
+        for m in mods {
+            mod_list.set_enabled(&m.name, true);
+        }
+        for (name, _) in &report.downloaded {
+            mod_list.set_enabled(name, true);
+        }
+        tx.save_mod_list(&mod_list)?;
+        lockfile::write(mods_dir, lock_path)
+    }
+    .await;
+    tx.finish(result)?;
+
+    Ok(report)
+}
+
+/// Carry out a plan computed by [`super::manifest::plan`].
+///
+/// Every release the plan needs is downloaded first, so a failed download
+/// changes nothing. The actions then run in plan order; mod-list.json and the
+/// lockfile at `lock_path` are written once at the end. If any step fails, the
+/// whole plan is rolled back.
+pub async fn apply(
+    portal: &SharedPortal,
+    plan: &Plan,
+    credentials: &Credentials,
+    mods_dir: &str,
+    lock_path: &Path,
+) -> Result<()> {
+    let mut mod_list = ModList::load_or_create(mods_dir)?;
+
+    let downloads: Vec<(String, Release)> = plan
+        .actions
+        .iter()
+        .flat_map(|action| match action {
+            Action::Install { releases, .. } | Action::Upgrade { releases, .. } => {
+                releases.as_slice()
+            }
+            _ => &[],
+        })
+        .cloned()
+        .collect();
+    let mut tx = Transaction::begin(mods_dir)?;
+    let result: Result<()> = async {
+        tx.download(portal, &downloads, credentials).await?;
+
+        for action in &plan.actions {
+            match action {
+                Action::Install { releases, .. } | Action::Upgrade { releases, .. } => {
+                    for (name, _) in releases {
+                        if !mod_list.mods.contains_key(name) {
+                            mod_list.set_enabled(name, true);
+                        }
+                    }
+                    if let Action::Upgrade { from, .. } = action {
+                        tx.delete_mod(from)?;
+                    }
+                }
+                Action::Remove(m) => {
+                    tx.delete_mod(m)?;
+                    mod_list.remove(&m.name);
+                }
+                Action::Enable(name) => mod_list.set_enabled(name, true),
+                Action::Disable(name) => mod_list.set_enabled(name, false),
+            }
+        }
+
+        tx.save_mod_list(&mod_list)?;
+        lockfile::write(mods_dir, lock_path)
+    }
+    .await;
+    tx.finish(result)
+}
+
+/// Outcome of [`sync`].
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Downloaded releases, dependencies included.
+    pub downloaded: Vec<(String, Release)>,
+    /// Listed mods that could not be installed, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Download every mod listed in mod-list.json that has no zip in the mods
+/// directory yet.
+///
+/// Dependencies pulled in along the way are added to mod-list.json as
+/// enabled; existing entries keep their state. A mod that fails leaves
+/// nothing behind, and if mod-list.json or the lockfile at `lock_path` cannot
+/// be written every download is rolled back.
+pub async fn sync(
+    portal: &SharedPortal,
+    factorio_version: &str,
+    credentials: &Credentials,
+    mods_dir: &str,
+    lock_path: &Path,
+) -> Result<SyncReport> {
+    let mut mod_list = ModList::load_or_create(mods_dir)?;
+    let mut installed_map = installed::read_installed_versions(mods_dir)?;
+
+    let mut missing: Vec<String> = mod_list
+        .mods
+        .keys()
+        .filter(|name| !types::is_builtin_mod(name) && !installed_map.contains_key(*name))
+        .cloned()
+        .collect();
+    missing.sort();
+
+    let mut report = SyncReport::default();
+    let mut tx = Transaction::begin(mods_dir)?;
+    for name in missing {
+        // May have been pulled in as a dependency of an earlier entry
+        if installed_map.contains_key(&name) {
+            continue;
+        }
+
+        let result = install(
+            &mut tx,
+            portal,
+            &name,
+            &semver::VersionReq::STAR,
+            factorio_version,
+            credentials,
+            &installed_map,
+        )
+        .await;
+
+        match result {
+            Ok(downloaded) => {
+                for (dep_name, release) in downloaded {
+                    installed_map.insert(dep_name.clone(), release.version.clone());
+                    if !mod_list.mods.contains_key(&dep_name) {
+                        mod_list.set_enabled(&dep_name, true);
+                    }
+                    report.downloaded.push((dep_name, release));
+                }
+            }
+            Err(e) => report.failed.push((name, e.to_string())),
+        }
+    }
+
+    let result = tx
+        .save_mod_list(&mod_list)
+        .and_then(|()| lockfile::write(mods_dir, lock_path));
+    tx.finish(result)?;
+    Ok(report)
+}
+
+/// A mod that [`update`] moved to a newer release.
+#[derive(Debug)]
+pub struct Upgrade {
+    pub name: String,
+    pub from: semver::Version,
+    pub to: Release,
+}
+
+/// A mod with a newer release that an enabled dependent does not accept.
+#[derive(Debug)]
+pub struct HeldBack {
+    pub name: String,
+    pub latest: semver::Version,
+    pub required_by: String,
+}
+
+/// Outcome of [`update`].
+#[derive(Debug, Default)]
+pub struct UpdateReport {
+    pub upgraded: Vec<Upgrade>,
+    /// Dependencies newly required by the upgraded releases.
+    pub dependencies: Vec<(String, Release)>,
+    pub held_back: Vec<HeldBack>,
+    pub up_to_date: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+enum Candidate<'a> {
+    UpToDate,
+    Upgrade(&'a Release),
+    HeldBack { latest: &'a Release, required_by: String },
+}
+
+/// Pick the newest release for the server's Factorio version that every
+/// enabled dependent still accepts.
+fn pick_update<'a>(
+    current: &semver::Version,
+    releases: &'a [Release],
+    factorio_version: &str,
+    dependents: &[(String, semver::VersionReq)],
+) -> Candidate<'a> {
+    let newer: Vec<&Release> = releases
+        .iter()
+        .filter(|r| r.factorio_version == factorio_version && r.version > *current)
+        .collect();
+
+    let allowed = newer
+        .iter()
+        .filter(|r| dependents.iter().all(|(_, req)| req.matches(&r.version)))
+        .max_by_key(|r| &r.version);
+    if let Some(release) = allowed {
+        return Candidate::Upgrade(release);
+    }
+
+    match newer.iter().max_by_key(|r| &r.version) {
+        Some(latest) => {
+            let required_by = dependents
+                .iter()
+                .find(|(_, req)| !req.matches(&latest.version))
+                .map(|(name, req)| format!("{} ({})", name, req))
+                .unwrap_or_default();
+            Candidate::HeldBack {
+                latest,
+                required_by,
+            }
+        }
+        None => Candidate::UpToDate,
+    }
+}
+
+/// Upgrade installed mods to their newest compatible release.
+///
+/// With no `names`, every installed mod is considered. Mods are processed
+/// dependencies first so a library is upgraded before the mods that may need
+/// its new version. Each upgrade is its own transaction: the old zip is only
+/// deleted once the new release and its dependencies are in place, and a
+/// failure leaves that mod as it was. New dependencies are enabled in
+/// mod-list.json and the lockfile at `lock_path` rewritten as part of the same
+/// transaction.
+pub async fn update(
+    portal: &SharedPortal,
+    names: &[String],
+    factorio_version: &str,
+    credentials: &Credentials,
+    mods_dir: &str,
+    lock_path: &Path,
+) -> Result<UpdateReport> {
+    let mods = installed::read_installed_mods(mods_dir)?;
+    for name in names {
+        if !mods.iter().any(|m| &m.name == name) {
+            bail!("Mod '{}' is not installed", name);
+        }
+    }
+
+    let targets = dependency_order(&mods, names);
+    let mut report = UpdateReport::default();
+
+    for name in targets {
+        // Earlier upgrades may have changed versions and dependencies
+        let mods = installed::read_installed_mods(mods_dir)?;
+        let mod_list = ModList::load_or_create(mods_dir)?;
+        let Some(current) = mods.iter().find(|m| m.name == name) else {
+            continue;
+        };
+
+        // Factorio also enforces the version of an optional dependency
+        // when both mods are loaded
+        let target_loaded = mod_list.loads(&name);
+        let dependents: Vec<(String, semver::VersionReq)> = mods
+            .iter()
+            .filter(|m| m.name != name && mod_list.loads(&m.name))
+            .flat_map(|m| {
+                m.dependencies
+                    .iter()
+                    .filter(|d| d.name == name)
+                    .filter(|d| match d.dep_type {
+                        DependencyType::Required => true,
+                        DependencyType::Optional => target_loaded,
+                        DependencyType::Incompatible => false,
+                    })
+                    .map(|d| (m.name.clone(), d.version_req.clone()))
+            })
+            .collect();
+
+        let details = match portal.fetch_mod_details(&name).await {
+            Ok(d) => d,
+            Err(e) => {
+                report.failed.push((name, e.to_string()));
+                continue;
+            }
+        };
+
+        let release = match pick_update(
+            &current.version,
+            &details.releases,
+            factorio_version,
+            &dependents,
+        ) {
+            Candidate::UpToDate => {
+                report.up_to_date.push(name);
+                continue;
+            }
+            Candidate::HeldBack {
+                latest,
+                required_by,
+            } => {
+                report.held_back.push(HeldBack {
+                    name,
+                    latest: latest.version.clone(),
+                    required_by,
+                });
+                continue;
+            }
+            Candidate::Upgrade(release) => release.clone(),
+        };
+
+        let installed_map: HashMap<String, semver::Version> = mods
+            .iter()
+            .filter(|m| m.name != name)
+            .map(|m| (m.name.clone(), m.version.clone()))
+            .collect();
+        let version_req = semver::VersionReq::parse(&format!("={}", release.version))?;
+
+        let mut tx = Transaction::begin(mods_dir)?;
+        let result = async {
+            let downloaded = install(
+                &mut tx,
+                portal,
+                &name,
+                &version_req,
+                factorio_version,
+                credentials,
+                &installed_map,
+            )
+            .await?;
+            tx.delete_mod(current)?;
+
+            let new_dependencies: Vec<&String> = downloaded
+                .iter()
+                .map(|(dep_name, _)| dep_name)
+                .filter(|dep_name| **dep_name != name)
+                .collect();
+            if !new_dependencies.is_empty() {
+                let mut mod_list = ModList::load_or_create(mods_dir)?;
+                for dep_name in new_dependencies {
+                    mod_list.set_enabled(dep_name, true);
+                }
+                tx.save_mod_list(&mod_list)?;
+            }
+            lockfile::write(mods_dir, lock_path)?;
+            Ok(downloaded)
+        }
+        .await;
+        let downloaded = match tx.finish(result) {
+            Ok(d) => d,
+            Err(e) => {
+                report.failed.push((name, e.to_string()));
+                continue;
+            }
+        };
+
+        report
+            .dependencies
+            .extend(downloaded.into_iter().filter(|(dep_name, _)| *dep_name != name));
+        report.upgraded.push(Upgrade {
+            name,
+            from: current.version.clone(),
+            to: release,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Order `names` (or every installed mod when empty) so that dependencies
+/// come before the mods requiring them.
+fn dependency_order(mods: &[InstalledMod], names: &[String]) -> Vec<String> {
+    let by_name: HashMap<&str, &InstalledMod> = mods.iter().map(|m| (m.name.as_str(), m)).collect();
+    let wanted: HashSet<&str> = if names.is_empty() {
+        by_name.keys().copied().collect()
+    } else {
+        names.iter().map(|n| n.as_str()).collect()
+    };
+
+    fn visit<'a>(
+        name: &'a str,
+        by_name: &HashMap<&'a str, &'a InstalledMod>,
+        wanted: &HashSet<&str>,
+        seen: &mut HashSet<&'a str>,
+        order: &mut Vec<String>,
+    ) {
+        if !seen.insert(name) {
+            return;
+        }
+        let Some(m) = by_name.get(name) else {
+            return;
+        };
+        for dep in &m.dependencies {
+            if dep.dep_type == DependencyType::Required {
+                if let Some((dep_name, _)) = by_name.get_key_value(dep.name.as_str()) {
+                    visit(dep_name, by_name, wanted, seen, order);
+                }
+            }
+        }
+        if wanted.contains(name) {
+            order.push(name.to_string());
+        }
+    }
+
+    let mut roots: Vec<&str> = by_name.keys().copied().collect();
+    roots.sort();
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    for name in roots {
+        visit(name, &by_name, &wanted, &mut seen, &mut order);
+    }
+    order
+}
+
+/// Work out which installed mods to delete when removing `names`.
+///
+/// `mod_list` decides which dependents count: only enabled mods block a
+/// removal (or get pulled into a cascade).
+pub fn plan_removal(
+    mods: &[InstalledMod],
+    mod_list: &ModList,
+    names: &[String],
+    mode: RemoveMode,
+) -> Result<Vec<InstalledMod>> {
+    for name in names {
+        if !mods.iter().any(|m| &m.name == name) {
+            bail!("Mod '{}' is not installed", name);
+        }
+    }
+
+    let mut selected: HashSet<String> = names.iter().cloned().collect();
+
+    match mode {
+        RemoveMode::Force => {}
+        RemoveMode::Refuse => {
+            for name in names {
+                let blockers: Vec<String> = installed::required_by(mods, mod_list, name)
+                    .into_iter()
+                    .filter(|d| !selected.contains(d))
+                    .collect();
+                if !blockers.is_empty() {
+                    bail!(
+                        "Cannot remove '{}': required by enabled mod(s) {}",
+                        name,
+                        blockers.join(", ")
+                    );
+                }
+            }
+        }
+        RemoveMode::Cascade => {
+            let mut queue: Vec<String> = names.to_vec();
+            while let Some(name) = queue.pop() {
+                for dependent in installed::required_by(mods, mod_list, &name) {
+                    if selected.insert(dependent.clone()) {
+                        queue.push(dependent);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(mods
+        .iter()
+        .filter(|m| selected.contains(&m.name))
+        .cloned()
+        .collect())
+}
+
+/// Delete the zips of `mods`, drop their entries from mod-list.json and
+/// rewrite the lockfile at `lock_path`, all or nothing.
+pub fn remove(mods: &[InstalledMod], mods_dir: &str, lock_path: &Path) -> Result<()> {
+    let mut mod_list = ModList::load_or_create(mods_dir)?;
+    let mut tx = Transaction::begin(mods_dir)?;
+    let result = (|| {
+        for m in mods {
+            tx.delete_mod(m)?;
+            mod_list.remove(&m.name);
+        }
+        tx.save_mod_list(&mod_list)?;
+        lockfile::write(mods_dir, lock_path)
+    })();
+    tx.finish(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::api::{Mirror, Portal};
+    use crate::factorio::fake_portal::MemoryPortal;
+    use crate::factorio::stub_portal;
+    use crate::factorio::test_support::{self, make_installed, make_release};
+    use std::sync::Arc;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            username: "user".to_string(),
+            token: "token".to_string(),
+        }
+    }
+
+    fn enabled(names: &[&str]) -> ModList {
+        let mut list = ModList::new();
+        for name in names {
+            list.set_enabled(name, true);
+        }
+        list
+    }
+
+    fn names(mods: &[InstalledMod]) -> Vec<&str> {
+        let mut names: Vec<&str> = mods.iter().map(|m| m.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn refuses_when_required_by_enabled_mod() {
+        let mods = vec![
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("mod-a", "1.0.0", &["lib >= 1.0.0"]),
+        ];
+        let list = enabled(&["lib", "mod-a"]);
+
+        let err = plan_removal(&mods, &list, &["lib".to_string()], RemoveMode::Refuse)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("lib"));
+        assert!(err.contains("mod-a"));
+    }
+
+    #[test]
+    fn disabled_and_optional_dependents_do_not_block() {
+        let mods = vec![
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("mod-a", "1.0.0", &["lib"]),
+            make_installed("mod-b", "1.0.0", &["? lib"]),
+        ];
+        let mut list = enabled(&["lib", "mod-b"]);
+        list.set_enabled("mod-a", false);
+
+        let plan = plan_removal(&mods, &list, &["lib".to_string()], RemoveMode::Refuse).unwrap();
+        assert_eq!(names(&plan), vec!["lib"]);
+    }
+
+    #[test]
+    fn unlisted_dependents_block() {
+        // Factorio loads zips that mod-list.json does not mention yet
+        let mods = vec![
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("mod-a", "1.0.0", &["lib"]),
+        ];
+        let list = enabled(&["lib"]);
+
+        let err = plan_removal(&mods, &list, &["lib".to_string()], RemoveMode::Refuse)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("mod-a"), "{}", err);
+        let plan = plan_removal(&mods, &list, &["lib".to_string()], RemoveMode::Cascade).unwrap();
+        assert_eq!(names(&plan), vec!["lib", "mod-a"]);
+    }
+
+    #[test]
+    fn removing_dependent_together_is_allowed() {
+        let mods = vec![
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("mod-a", "1.0.0", &["lib"]),
+        ];
+        let list = enabled(&["lib", "mod-a"]);
+
+        let plan = plan_removal(
+            &mods,
+            &list,
+            &["lib".to_string(), "mod-a".to_string()],
+            RemoveMode::Refuse,
+        )
+        .unwrap();
+        assert_eq!(names(&plan), vec!["lib", "mod-a"]);
+    }
+
+    #[test]
+    fn cascade_removes_transitive_dependents() {
+        let mods = vec![
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("mid", "1.0.0", &["lib"]),
+            make_installed("top", "1.0.0", &["~ mid"]),
+            make_installed("other", "1.0.0", &[]),
+        ];
+        let list = enabled(&["lib", "mid", "top", "other"]);
+
+        let plan = plan_removal(&mods, &list, &["lib".to_string()], RemoveMode::Cascade).unwrap();
+        assert_eq!(names(&plan), vec!["lib", "mid", "top"]);
+    }
+
+    #[test]
+    fn force_removes_only_requested() {
+        let mods = vec![
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("mod-a", "1.0.0", &["lib"]),
+        ];
+        let list = enabled(&["lib", "mod-a"]);
+
+        let plan = plan_removal(&mods, &list, &["lib".to_string()], RemoveMode::Force).unwrap();
+        assert_eq!(names(&plan), vec!["lib"]);
+    }
+
+    #[test]
+    fn update_picks_newest_for_factorio_version() {
+        let releases = vec![
+            make_release("lib", "1.0.0", "1.1", &[]),
+            make_release("lib", "1.1.0", "1.1", &[]),
+            make_release("lib", "2.0.0", "2.0", &[]),
+        ];
+        match pick_update(&semver::Version::new(1, 0, 0), &releases, "1.1", &[]) {
+            Candidate::Upgrade(r) => assert_eq!(r.version, semver::Version::new(1, 1, 0)),
+            other => panic!("expected upgrade, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_respects_dependent_requirements() {
+        let releases = vec![
+            make_release("lib", "1.0.0", "1.1", &[]),
+            make_release("lib", "1.5.0", "1.1", &[]),
+            make_release("lib", "2.0.0", "1.1", &[]),
+        ];
+        let dependents = vec![(
+            "mod-a".to_string(),
+            semver::VersionReq::parse("<2.0.0").unwrap(),
+        )];
+        match pick_update(&semver::Version::new(1, 0, 0), &releases, "1.1", &dependents) {
+            Candidate::Upgrade(r) => assert_eq!(r.version, semver::Version::new(1, 5, 0)),
+            other => panic!("expected upgrade, got {:?}", other),
+        }
+        match pick_update(&semver::Version::new(1, 5, 0), &releases, "1.1", &dependents) {
+            Candidate::HeldBack {
+                latest,
+                required_by,
+            } => {
+                assert_eq!(latest.version, semver::Version::new(2, 0, 0));
+                assert!(required_by.contains("mod-a"));
+            }
+            other => panic!("expected held back, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_does_not_rely_on_release_order() {
+        let releases = vec![
+            make_release("lib", "1.5.0", "1.1", &[]),
+            make_release("lib", "2.0.0", "1.1", &[]),
+            make_release("lib", "1.0.0", "1.1", &[]),
+            make_release("lib", "1.2.0", "1.1", &[]),
+        ];
+        match pick_update(&semver::Version::new(1, 0, 0), &releases, "1.1", &[]) {
+            Candidate::Upgrade(r) => assert_eq!(r.version, semver::Version::new(2, 0, 0)),
+            other => panic!("expected upgrade, got {:?}", other),
+        }
+        let dependents = vec![(
+            "mod-a".to_string(),
+            semver::VersionReq::parse("<2.0.0").unwrap(),
+        )];
+        match pick_update(&semver::Version::new(1, 0, 0), &releases, "1.1", &dependents) {
+            Candidate::Upgrade(r) => assert_eq!(r.version, semver::Version::new(1, 5, 0)),
+            other => panic!("expected upgrade, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_up_to_date() {
+        let releases = vec![
+            make_release("lib", "1.0.0", "1.1", &[]),
+            make_release("lib", "2.0.0", "2.0", &[]),
+        ];
+        assert!(matches!(
+            pick_update(&semver::Version::new(1, 0, 0), &releases, "1.1", &[]),
+            Candidate::UpToDate
+        ));
+    }
+
+    #[test]
+    fn dependency_order_puts_libraries_first() {
+        let mods = vec![
+            make_installed("a-top", "1.0.0", &["mid"]),
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("mid", "1.0.0", &["lib", "? a-top"]),
+        ];
+        assert_eq!(dependency_order(&mods, &[]), vec!["lib", "mid", "a-top"]);
+        assert_eq!(
+            dependency_order(&mods, &["a-top".to_string(), "lib".to_string()]),
+            vec!["lib", "a-top"]
+        );
+    }
+
+    #[test]
+    fn unknown_mod_errors() {
+        let mods = vec![make_installed("lib", "1.0.0", &[])];
+        let list = enabled(&["lib"]);
+
+        let err = plan_removal(&mods, &list, &["nope".to_string()], RemoveMode::Force)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not installed"));
+    }
+
+    #[tokio::test]
+    async fn sync_installs_from_stub_portal() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let portal: SharedPortal = Arc::new(Portal::new(vec![
+            Mirror::new(&stub_portal::dead_url().await),
+            Mirror::new(&stub.url),
+        ]));
+        let credentials = credentials();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        let lock_path = dir.path().join("fml.lock");
+        enabled(&["base", "mod-a"]).save(mods_dir).unwrap();
+
+        let report = sync(&portal, "1.1", &credentials, mods_dir, &lock_path)
+            .await
+            .unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        let downloaded: Vec<&str> = report.downloaded.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(downloaded, vec!["lib", "mod-a"]);
+
+        let installed = installed::read_installed_versions(mods_dir).unwrap();
+        assert_eq!(installed["lib"], semver::Version::new(1, 0, 0));
+        assert_eq!(installed["mod-a"], semver::Version::new(1, 0, 0));
+        assert!(ModList::load_or_create(mods_dir).unwrap().is_enabled("lib"));
+        assert_eq!(Lockfile::load(&lock_path).unwrap().mods.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_download_removes_the_rest() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let portal: SharedPortal =
+            Arc::new(Portal::new(vec![Mirror::new(&stub.url)]).with_concurrency(1));
+        let credentials = credentials();
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        let existing = test_support::write_mod_zip(dir.path(), "mod-a", "1.0.0", &[]);
+
+        let lib = portal.fetch_mod_details("lib").await.unwrap().releases[0].clone();
+        let mod_a = portal.fetch_mod_details("mod-a").await.unwrap().releases[0].clone();
+        let mut broken = lib.clone();
+        broken.file_name = "broken_1.0.0.zip".to_string();
+        broken.sha1 = "0".repeat(40);
+
+        let releases = vec![
+            ("lib".to_string(), lib),
+            ("mod-a".to_string(), mod_a),
+            ("broken".to_string(), broken),
+        ];
+        let err = download_all(&portal, &releases, &credentials, mods_dir)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Failed to download 'broken'"), "{}", err);
+        assert!(err.contains("SHA1 mismatch"), "{}", err);
+
+        // mod-a was there before and stays; lib is removed again
+        assert!(existing.is_file());
+        assert!(!dir.path().join("lib_1.0.0.zip").exists());
+        assert!(!dir.path().join("broken_1.0.0.zip").exists());
+
+        download_all(&portal, &releases[..1], &credentials, mods_dir)
+            .await
+            .unwrap();
+        assert!(dir.path().join("lib_1.0.0.zip").is_file());
+    }
+
+    #[tokio::test]
+    async fn failed_mod_list_write_rolls_back_downloads() {
+        let stub = stub_portal::serve(stub_portal::fixture_root()).await;
+        let portal: SharedPortal = Arc::new(Portal::new(vec![Mirror::new(&stub.url)]));
+        let credentials = credentials();
+        let dir = tempfile::tempdir().unwrap();
+        let mods = dir.path().join("mods");
+        std::fs::create_dir_all(&mods).unwrap();
+        let mods_dir = mods.to_str().unwrap();
+        let old = test_support::write_mod_zip(&mods, "lib", "0.9.0", &[]);
+        // Writing through a link into a missing directory fails
+        let target = dir.path().join("missing/mod-list.json");
+        std::os::unix::fs::symlink(target, mods.join("mod-list.json")).unwrap();
+
+        let save_mods = vec![
+            SaveMod {
+                name: "lib".to_string(),
+                version: semver::Version::new(1, 0, 0),
+            },
+            SaveMod {
+                name: "mod-a".to_string(),
+                version: semver::Version::new(1, 0, 0),
+            },
+        ];
+        let lock_path = dir.path().join("fml.lock");
+        let err = install_save(&portal, &save_mods, "1.1", &credentials, mods_dir, &lock_path)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Failed to write"), "{}", err);
+
+        assert!(old.is_file());
+        assert!(!mods.join("lib_1.0.0.zip").exists());
+        assert!(!mods.join("mod-a_1.0.0.zip").exists());
+        assert!(!crate::factorio::transaction::staging_dir(&mods).exists());
+    }
+
+    #[tokio::test]
+    async fn loaded_mods_block_what_they_declare_incompatible() {
+        let portal: SharedPortal = Arc::new(
+            MemoryPortal::new()
+                .with_release("lib", "1.0.0", &[])
+                .with_release("mod-a", "1.0.0", &["lib"]),
+        );
+        let credentials = credentials();
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        test_support::write_mod_zip(dir.path(), "old-mod", "1.0.0", &["! lib"]);
+        let installed_map = installed::read_installed_versions(mods_dir).unwrap();
+
+        // Not in mod-list.json yet, so Factorio loads it
+        let mut tx = Transaction::begin(mods_dir).unwrap();
+        let result = install(
+            &mut tx,
+            &portal,
+            "mod-a",
+            &semver::VersionReq::STAR,
+            "1.1",
+            &credentials,
+            &installed_map,
+        )
+        .await;
+        let err = tx.finish(result).unwrap_err().to_string();
+        assert!(
+            err.contains("lib 1.0.0 is marked incompatible by installed mod old-mod 1.0.0"),
+            "{}",
+            err
+        );
+        assert!(!dir.path().join("lib_1.0.0.zip").exists());
+
+        let mut mod_list = ModList::new();
+        mod_list.set_enabled("old-mod", false);
+        mod_list.save(mods_dir).unwrap();
+        let mut tx = Transaction::begin(mods_dir).unwrap();
+        let result = install(
+            &mut tx,
+            &portal,
+            "mod-a",
+            &semver::VersionReq::STAR,
+            "1.1",
+            &credentials,
+            &installed_map,
+        )
+        .await;
+        assert_eq!(tx.finish(result).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn update_honours_unlisted_dependents_and_enables_new_dependencies() {
+        let portal: SharedPortal = Arc::new(
+            MemoryPortal::new()
+                .with_release("helper", "1.0.0", &[])
+                .with_release("lib", "1.0.0", &[])
+                .with_release("lib", "2.0.0", &["helper"]),
+        );
+        let credentials = credentials();
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        test_support::write_mod_zip(dir.path(), "lib", "1.0.0", &[]);
+        test_support::write_mod_zip(dir.path(), "mod-a", "1.0.0", &["lib < 2.0.0"]);
+        let lock_path = dir.path().join("fml.lock");
+
+        // mod-a is not in mod-list.json, so Factorio loads it
+        let names = ["lib".to_string()];
+        let report = update(&portal, &names, "1.1", &credentials, mods_dir, &lock_path)
+            .await
+            .unwrap();
+        assert!(report.upgraded.is_empty());
+        assert_eq!(report.held_back[0].required_by, "mod-a (<2.0.0)");
+
+        let mut mod_list = ModList::new();
+        mod_list.set_enabled("mod-a", false);
+        mod_list.save(mods_dir).unwrap();
+        let report = update(&portal, &names, "1.1", &credentials, mods_dir, &lock_path)
+            .await
+            .unwrap();
+        assert_eq!(report.upgraded[0].to.version, semver::Version::new(2, 0, 0));
+        assert_eq!(report.dependencies[0].0, "helper");
+        let mod_list = ModList::load_or_create(mods_dir).unwrap();
+        assert!(mod_list.is_enabled("helper"));
+        assert!(!mod_list.is_enabled("mod-a"));
+    }
+
+    #[tokio::test]
+    async fn update_honours_optional_dependents() {
+        let portal: SharedPortal = Arc::new(
+            MemoryPortal::new()
+                .with_release("lib", "1.0.0", &[])
+                .with_release("lib", "2.0.0", &[]),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        test_support::write_mod_zip(dir.path(), "lib", "1.0.0", &[]);
+        test_support::write_mod_zip(dir.path(), "mod-a", "1.0.0", &["? lib < 2.0.0"]);
+        let lock_path = dir.path().join("fml.lock");
+
+        let names = ["lib".to_string()];
+        let report = update(&portal, &names, "1.1", &credentials(), mods_dir, &lock_path)
+            .await
+            .unwrap();
+        assert!(report.upgraded.is_empty());
+        assert_eq!(report.held_back[0].required_by, "mod-a (<2.0.0)");
+    }
+
+    #[tokio::test]
+    async fn failed_lockfile_write_rolls_back_update() {
+        let portal: SharedPortal = Arc::new(
+            MemoryPortal::new()
+                .with_release("lib", "1.0.0", &[])
+                .with_release("lib", "2.0.0", &[]),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        let old = test_support::write_mod_zip(dir.path(), "lib", "1.0.0", &[]);
+        let lock_path = dir.path().join("missing/fml.lock");
+
+        let names = ["lib".to_string()];
+        let report = update(&portal, &names, "1.1", &credentials(), mods_dir, &lock_path)
+            .await
+            .unwrap();
+        assert!(report.upgraded.is_empty());
+        assert_eq!(report.failed[0].0, "lib");
+        assert!(old.is_file());
+        assert!(!dir.path().join("lib_2.0.0.zip").exists());
+    }
+}
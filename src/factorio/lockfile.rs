@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::installed;
+
+/// Exact set of installed mod zips, written to `fml.lock`.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub mods: Vec<LockedMod>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedMod {
+    pub name: String,
+    pub version: semver::Version,
+    pub file_name: String,
+    pub sha1: String,
+}
+
+impl Lockfile {
+    /// Record every mod zip currently in the mods directory.
+    pub fn from_mods_dir(mods_dir: &str) -> Result<Self> {
+        let mut mods = installed::read_installed_mods(mods_dir)?
+            .into_iter()
+            .map(|m| {
+                let sha1 = installed::file_sha1(&Path::new(mods_dir).join(&m.file_name))?;
+                Ok(LockedMod {
+                    name: m.name,
+                    version: m.version,
+                    file_name: m.file_name,
+                    sha1,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        mods.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+        Ok(Lockfile { mods })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| {
+            format!(
+                "Failed to read {}. Run an install first to create it.",
+                path.display()
+            )
+        })?;
+        serde_json::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Refresh the lockfile at `path` from the current contents of `mods_dir`.
+pub fn write(mods_dir: &str, path: &Path) -> Result<()> {
+    Lockfile::from_mods_dir(mods_dir)?.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::test_support;
+
+    #[test]
+    fn records_installed_zips() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip = test_support::write_mod_zip(dir.path(), "mod-b", "1.0.0", &[]);
+        test_support::write_mod_zip(dir.path(), "mod-a", "2.1.0", &[]);
+
+        let lock = Lockfile::from_mods_dir(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(lock.mods.len(), 2);
+        assert_eq!(lock.mods[0].name, "mod-a");
+        assert_eq!(lock.mods[0].version, semver::Version::new(2, 1, 0));
+        assert_eq!(lock.mods[1].file_name, "mod-b_1.0.0.zip");
+
+        let bytes = std::fs::read(zip).unwrap();
+        let expected = sha1_smol::Sha1::from(&bytes).digest().to_string();
+        assert_eq!(lock.mods[1].sha1, expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fml.lock");
+        let lock = Lockfile {
+            mods: vec![LockedMod {
+                name: "flib".to_string(),
+                version: semver::Version::new(0, 15, 0),
+                file_name: "flib_0.15.0.zip".to_string(),
+                sha1: "abc".to_string(),
+            }],
+        };
+        lock.save(&path).unwrap();
+        assert_eq!(Lockfile::load(&path).unwrap(), lock);
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::installed::{self, InstalledMod};
+use super::mod_list::ModList;
+use super::portal::ModPortal;
+use super::resolver;
+use super::types::{self, DependencyType, Release};
+
+/// A mod declared in the `mods` list of `fml.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestMod {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<semver::VersionReq>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl ManifestMod {
+    fn version_req(&self) -> semver::VersionReq {
+        self.version.clone().unwrap_or(semver::VersionReq::STAR)
+    }
+}
+
+#[derive(Debug)]
+pub enum Action {
+    /// Download a declared mod; `releases` also holds its missing dependencies.
+    Install {
+        name: String,
+        releases: Vec<(String, Release)>,
+    },
+    /// Replace an installed version that does not satisfy the declared requirement.
+    Upgrade {
+        name: String,
+        from: InstalledMod,
+        releases: Vec<(String, Release)>,
+    },
+    Remove(InstalledMod),
+    Enable(String),
+    Disable(String),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let deps = |name: &str, releases: &[(String, Release)]| {
+            let deps: Vec<String> = releases
+                .iter()
+                .filter(|(n, _)| n != name)
+                .map(|(n, r)| format!("{} {}", n, r.version))
+                .collect();
+            if deps.is_empty() {
+                String::new()
+            } else {
+                format!(" (with {})", deps.join(", "))
+            }
+        };
+        let version = |name: &str, releases: &[(String, Release)]| {
+            releases
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, r)| r.version.to_string())
+                .unwrap_or_default()
+        };
+
+        match self {
+            Action::Install { name, releases } => write!(
+                f,
+                "+ install {} {}{}",
+                name,
+                version(name, releases),
+                deps(name, releases)
+            ),
+            Action::Upgrade {
+                name,
+                from,
+                releases,
+            } => write!(
+                f,
+                "~ upgrade {} {} -> {}{}",
+                name,
+                from.version,
+                version(name, releases),
+                deps(name, releases)
+            ),
+            Action::Remove(m) => write!(f, "- remove {} {}", m.name, m.version),
+            Action::Enable(name) => write!(f, "✔ enable {}", name),
+            Action::Disable(name) => write!(f, "✘ disable {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+/// Compute what it takes to turn the installed mods into the declared set.
+///
+/// Missing and mismatched mods are resolved up front against `portal`, so the
+/// plan knows every dependency it will pull in.
+/// Installed mods that are neither declared nor required by a declared mod
+/// are removed. Only declared mods have their enabled state managed.
+pub async fn plan(
+    manifest: &[ManifestMod],
+    installed: &[InstalledMod],
+    mod_list: &ModList,
+    factorio_version: &str,
+    portal: &dyn ModPortal,
+) -> Result<Plan> {
+    let mut installed_map: HashMap<String, semver::Version> = installed
+        .iter()
+        .map(|m| (m.name.clone(), m.version.clone()))
+        .collect();
+
+    let loaded = installed::loaded(installed, mod_list);
+
+    let mut installs = Vec::new();
+    let mut upgrades = Vec::new();
+    let mut new_releases: Vec<(String, Release)> = Vec::new();
+
+    for wanted in manifest.iter().filter(|m| !types::is_builtin_mod(&m.name)) {
+        let version_req = wanted.version_req();
+        let current = installed.iter().find(|m| m.name == wanted.name);
+
+        if let Some(current) = current {
+            if version_req.matches(&current.version) {
+                continue;
+            }
+        }
+
+        // An earlier install may already have pulled this mod in
+        if current.is_none() && new_releases.iter().any(|(n, _)| n == &wanted.name) {
+            continue;
+        }
+
+        let mut resolve_map = installed_map.clone();
+        resolve_map.remove(&wanted.name);
+        let result = resolver::resolve(
+            &wanted.name,
+            &version_req,
+            factorio_version,
+            &resolve_map,
+            &loaded,
+            portal,
+        )
+        .await?;
+
+        for (name, release) in &result.to_download {
+            installed_map.insert(name.clone(), release.version.clone());
+            new_releases.push((name.clone(), release.clone()));
+        }
+
+        match current {
+            Some(current) => upgrades.push(Action::Upgrade {
+                name: wanted.name.clone(),
+                from: current.clone(),
+                releases: result.to_download,
+            }),
+            None => installs.push(Action::Install {
+                name: wanted.name.clone(),
+                releases: result.to_download,
+            }),
+        }
+    }
+
+    // Everything declared, plus whatever those mods (transitively) require
+    let mut keep: HashSet<String> = manifest.iter().map(|m| m.name.clone()).collect();
+    keep.extend(new_releases.iter().map(|(n, _)| n.clone()));
+    let mut queue: Vec<String> = keep.iter().cloned().collect();
+    while let Some(name) = queue.pop() {
+        let deps = match new_releases.iter().rev().find(|(n, _)| *n == name) {
+            Some((_, release)) => &release.dependencies,
+            None => match installed.iter().find(|m| m.name == name) {
+                Some(m) => &m.dependencies,
+                None => continue,
+            },
+        };
+        for dep in deps {
+            if dep.dep_type == DependencyType::Required && keep.insert(dep.name.clone()) {
+                queue.push(dep.name.clone());
+            }
+        }
+    }
+
+    let removals = installed
+        .iter()
+        .filter(|m| !keep.contains(&m.name) && !types::is_builtin_mod(&m.name))
+        .cloned()
+        .map(Action::Remove);
+
+    let mut toggles = Vec::new();
+    for wanted in manifest {
+        let is_new = !installed.iter().any(|m| m.name == wanted.name)
+            && !types::is_builtin_mod(&wanted.name);
+        // Newly installed mods end up enabled
+        let currently_enabled = is_new || mod_list.is_enabled(&wanted.name);
+        if wanted.enabled && !currently_enabled {
+            toggles.push(Action::Enable(wanted.name.clone()));
+        } else if !wanted.enabled && currently_enabled {
+            toggles.push(Action::Disable(wanted.name.clone()));
+        }
+    }
+
+    let mut actions = installs;
+    actions.extend(upgrades);
+    actions.extend(removals);
+    actions.extend(toggles);
+    Ok(Plan { actions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::fake_portal::MemoryPortal;
+    use crate::factorio::test_support::{make_installed, make_mod, make_release};
+    use crate::factorio::types::Mod;
+
+    fn declare(name: &str, version: Option<&str>, enabled: bool) -> ManifestMod {
+        ManifestMod {
+            name: name.to_string(),
+            version: version.map(|v| semver::VersionReq::parse(v).unwrap()),
+            enabled,
+        }
+    }
+
+    async fn run_plan(
+        manifest: &[ManifestMod],
+        installed: &[InstalledMod],
+        mod_list: &ModList,
+        registry: Vec<Mod>,
+    ) -> Plan {
+        let portal = registry
+            .into_iter()
+            .fold(MemoryPortal::new(), MemoryPortal::with_mod);
+        plan(manifest, installed, mod_list, "1.1", &portal).await.unwrap()
+    }
+
+    fn describe(plan: &Plan) -> Vec<String> {
+        plan.actions.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn installs_missing_with_dependencies() {
+        let registry = vec![
+            make_mod("mod-a", vec![make_release("mod-a", "1.0.0", "1.1", &["lib"])]),
+            make_mod("lib", vec![make_release("lib", "0.5.0", "1.1", &[])]),
+        ];
+        let manifest = vec![declare("mod-a", None, true)];
+
+        let plan = run_plan(&manifest, &[], &ModList::new(), registry).await;
+        assert_eq!(describe(&plan), vec!["+ install mod-a 1.0.0 (with lib 0.5.0)"]);
+    }
+
+    #[tokio::test]
+    async fn removes_undeclared_but_keeps_dependencies() {
+        let installed = vec![
+            make_installed("mod-a", "1.0.0", &["lib"]),
+            make_installed("lib", "1.0.0", &[]),
+            make_installed("stray", "1.0.0", &[]),
+        ];
+        let mut list = ModList::new();
+        for m in &installed {
+            list.set_enabled(&m.name, true);
+        }
+        let manifest = vec![declare("mod-a", None, true)];
+
+        let plan = run_plan(&manifest, &installed, &list, vec![]).await;
+        assert_eq!(describe(&plan), vec!["- remove stray 1.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn upgrades_mismatched_version() {
+        let registry = vec![make_mod(
+            "mod-a",
+            vec![
+                make_release("mod-a", "1.0.0", "1.1", &[]),
+                make_release("mod-a", "2.0.0", "1.1", &[]),
+            ],
+        )];
+        let installed = vec![make_installed("mod-a", "1.0.0", &[])];
+        let mut list = ModList::new();
+        list.set_enabled("mod-a", true);
+        let manifest = vec![declare("mod-a", Some(">=2.0.0"), true)];
+
+        let plan = run_plan(&manifest, &installed, &list, registry).await;
+        assert_eq!(describe(&plan), vec!["~ upgrade mod-a 1.0.0 -> 2.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn toggles_enabled_state() {
+        let installed = vec![
+            make_installed("mod-a", "1.0.0", &[]),
+            make_installed("mod-b", "1.0.0", &[]),
+        ];
+        let mut list = ModList::new();
+        list.set_enabled("mod-a", false);
+        list.set_enabled("mod-b", true);
+        let manifest = vec![
+            declare("mod-a", None, true),
+            declare("mod-b", None, false),
+            declare("space-age", None, false),
+        ];
+
+        let plan = run_plan(&manifest, &installed, &list, vec![]).await;
+        assert_eq!(
+            describe(&plan),
+            vec!["✔ enable mod-a", "✘ disable mod-b"]
+        );
+    }
+
+    #[test]
+    fn manifest_defaults() {
+        let m: ManifestMod = serde_json::from_str(r#"{"name": "flib"}"#).unwrap();
+        assert!(m.enabled);
+        assert!(m.version.is_none());
+
+        let m: ManifestMod =
+            serde_json::from_str(r#"{"name": "flib", "version": ">=0.15", "enabled": false}"#)
+                .unwrap();
+        assert!(!m.enabled);
+        assert!(m.version.unwrap().matches(&semver::Version::new(0, 15, 3)));
+    }
+}
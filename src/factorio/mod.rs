@@ -1,5 +1,25 @@
 pub mod api;
+pub mod cache;
+pub mod credentials;
+pub mod doctor;
+#[cfg(test)]
+pub mod fake_portal;
+pub mod graph;
+pub mod http;
 pub mod installed;
+pub mod installer;
+pub mod lockfile;
+pub mod manifest;
 pub mod mod_list;
+pub mod mod_settings;
+pub mod outdated;
+pub mod portal;
+pub mod property_tree;
 pub mod resolver;
+pub mod save;
+pub mod store;
+pub mod stub_portal;
+#[cfg(test)]
+pub mod test_support;
+pub mod transaction;
 pub mod types;
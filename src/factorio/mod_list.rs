@@ -104,6 +104,12 @@ impl ModList {
         self.mods.get(name).map(|e| e.enabled).unwrap_or(false)
     }
 
+    /// Whether Factorio loads an installed mod called `name`: mods the list
+    /// does not mention yet are enabled on the next start.
+    pub fn loads(&self, name: &str) -> bool {
+        self.mods.get(name).is_none_or(|e| e.enabled)
+    }
+
     pub fn set_enabled(&mut self, name: &str, enabled: bool) {
         if let Some(entry) = self.mods.get_mut(name) {
             entry.enabled = enabled;
@@ -0,0 +1,610 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use super::installed::InstalledMod;
+use super::property_tree::{self, PropertyTree, Value};
+
+const SETTINGS_FILE: &str = "mod-settings.dat";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SettingScope {
+    Startup,
+    RuntimeGlobal,
+    RuntimePerUser,
+}
+
+impl SettingScope {
+    /// Key of the scope in mod-settings.dat and `setting_type` in settings.lua.
+    pub fn key(self) -> &'static str {
+        match self {
+            SettingScope::Startup => "startup",
+            SettingScope::RuntimeGlobal => "runtime-global",
+            SettingScope::RuntimePerUser => "runtime-per-user",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "startup" => Some(SettingScope::Startup),
+            "runtime-global" => Some(SettingScope::RuntimeGlobal),
+            "runtime-per-user" => Some(SettingScope::RuntimePerUser),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    Bool,
+    Int,
+    Double,
+    String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingValue {
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    String(String),
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingValue::Bool(b) => write!(f, "{}", b),
+            SettingValue::Int(i) => write!(f, "{}", i),
+            SettingValue::Double(d) => write!(f, "{}", d),
+            SettingValue::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// A setting declared in a mod's `settings.lua`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingPrototype {
+    pub mod_name: String,
+    pub name: String,
+    pub kind: SettingKind,
+    pub scope: SettingScope,
+    pub default: Option<SettingValue>,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    /// Empty when any value is allowed.
+    pub allowed_values: Vec<String>,
+    pub order: String,
+}
+
+impl SettingPrototype {
+    /// Parse user input into a value of this setting's type, enforcing the
+    /// bounds and allowed values from the prototype.
+    pub fn parse_value(&self, input: &str) -> Result<SettingValue> {
+        let input = input.trim();
+        let value = match self.kind {
+            SettingKind::Bool => match input {
+                "true" => SettingValue::Bool(true),
+                "false" => SettingValue::Bool(false),
+                _ => bail!("{} expects true or false", self.name),
+            },
+            SettingKind::Int => SettingValue::Int(
+                input
+                    .parse()
+                    .with_context(|| format!("{} expects a whole number", self.name))?,
+            ),
+            SettingKind::Double => SettingValue::Double(
+                input
+                    .parse()
+                    .with_context(|| format!("{} expects a number", self.name))?,
+            ),
+            SettingKind::String => SettingValue::String(input.to_string()),
+        };
+
+        let number = match value {
+            SettingValue::Int(i) => Some(i as f64),
+            SettingValue::Double(d) => Some(d),
+            _ => None,
+        };
+        if let Some(n) = number {
+            if self.minimum.is_some_and(|min| n < min) || self.maximum.is_some_and(|max| n > max) {
+                bail!(
+                    "{} must be between {} and {}",
+                    self.name,
+                    self.minimum.map_or("-∞".to_string(), |m| m.to_string()),
+                    self.maximum.map_or("∞".to_string(), |m| m.to_string())
+                );
+            }
+        }
+        if !self.allowed_values.is_empty() && !self.allowed_values.iter().any(|v| v == input) {
+            bail!(
+                "{} must be one of: {}",
+                self.name,
+                self.allowed_values.join(", ")
+            );
+        }
+        Ok(value)
+    }
+}
+
+// --- mod-settings.dat ---
+
+/// Contents of `mod-settings.dat`: the game version that wrote it and a
+/// property tree of `scope -> setting -> { value = ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModSettings {
+    pub version: [u16; 4],
+    pub tree: PropertyTree,
+}
+
+impl ModSettings {
+    pub fn new(factorio_version: &str) -> Self {
+        let mut version = [0u16; 4];
+        for (slot, part) in version.iter_mut().zip(factorio_version.split('.')) {
+            *slot = part.parse().unwrap_or(0);
+        }
+        ModSettings {
+            version,
+            tree: Value::Dictionary(Vec::new()).into(),
+        }
+    }
+
+    /// Read `mod-settings.dat` from the mods directory, or start an empty one
+    /// for `factorio_version` if there is none yet.
+    pub fn load_or_create(mods_dir: &str, factorio_version: &str) -> Result<Self> {
+        let path = Path::new(mods_dir).join(SETTINGS_FILE);
+        if !path.exists() {
+            return Ok(ModSettings::new(factorio_version));
+        }
+        let data =
+            std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        ModSettings::parse(&data).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = property_tree::Reader::new(data);
+        let mut version = [0u16; 4];
+        for slot in &mut version {
+            *slot = reader.u16()?;
+        }
+        reader.u8()?;
+        let tree = reader.tree()?;
+        if !reader.is_at_end() {
+            bail!("Trailing data after the settings tree");
+        }
+        Ok(ModSettings { version, tree })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in self.version {
+            out.extend_from_slice(&part.to_le_bytes());
+        }
+        out.push(0);
+        property_tree::write_tree(&mut out, &self.tree);
+        out
+    }
+
+    pub fn save(&self, mods_dir: &str) -> Result<()> {
+        let path = Path::new(mods_dir).join(SETTINGS_FILE);
+        std::fs::write(&path, self.to_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The stored value of a setting, if mod-settings.dat has one.
+    pub fn value(&self, proto: &SettingPrototype) -> Option<SettingValue> {
+        let stored = self
+            .tree
+            .get(proto.scope.key())?
+            .get(&proto.name)?
+            .get("value")?;
+        match (proto.kind, &stored.value) {
+            (SettingKind::Bool, Value::Bool(b)) => Some(SettingValue::Bool(*b)),
+            (SettingKind::Int, Value::Number(n)) => Some(SettingValue::Int(*n as i64)),
+            (SettingKind::Int, Value::SignedInteger(i)) => Some(SettingValue::Int(*i)),
+            (SettingKind::Double, Value::Number(n)) => Some(SettingValue::Double(*n)),
+            (SettingKind::String, Value::String(s)) => Some(SettingValue::String(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn set_value(&mut self, proto: &SettingPrototype, value: &SettingValue) {
+        // Factorio 2.0 stores int settings as integers, older versions as doubles
+        let stored = match value {
+            SettingValue::Bool(b) => Value::Bool(*b),
+            SettingValue::Int(i) if self.version[0] >= 2 => Value::SignedInteger(*i),
+            SettingValue::Int(i) => Value::Number(*i as f64),
+            SettingValue::Double(d) => Value::Number(*d),
+            SettingValue::String(s) => Value::String(s.clone()),
+        };
+        let empty = || Value::Dictionary(Vec::new());
+        self.tree
+            .entry(proto.scope.key(), empty())
+            .entry(&proto.name, empty())
+            .entry("value", Value::None)
+            .value = stored;
+    }
+
+    /// Drop the stored value so Factorio falls back to the default.
+    pub fn reset(&mut self, proto: &SettingPrototype) {
+        let empty = || Value::Dictionary(Vec::new());
+        self.tree.entry(proto.scope.key(), empty()).remove(&proto.name);
+    }
+}
+
+// --- settings.lua ---
+
+/// Read the setting prototypes of every installed mod that has a
+/// `settings.lua`. Mods whose settings cannot be read are skipped, with a
+/// message passed to `warn`.
+pub fn read_prototypes(
+    mods_dir: &str,
+    installed: &[InstalledMod],
+    mut warn: impl FnMut(String),
+) -> Vec<SettingPrototype> {
+    let mut prototypes = Vec::new();
+    for m in installed {
+        let path = Path::new(mods_dir).join(&m.file_name);
+        match read_settings_lua(&path) {
+            Ok(Some(source)) => prototypes.extend(parse_settings_lua(&m.name, &source)),
+            Ok(None) => {}
+            Err(e) => warn(format!("Skipping settings of {}: {}", m.name, e)),
+        }
+    }
+    prototypes.sort_by(|a, b| {
+        (&a.mod_name, a.scope, &a.order, &a.name).cmp(&(&b.mod_name, b.scope, &b.order, &b.name))
+    });
+    prototypes
+}
+
+fn read_settings_lua(zip_path: &Path) -> Result<Option<String>> {
+    let file = std::fs::File::open(zip_path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    // settings.lua sits in the top-level folder of the zip
+    let name = archive
+        .file_names()
+        .find(|n| {
+            let parts: Vec<&str> = n.split('/').collect();
+            parts.len() == 2 && parts[1] == "settings.lua"
+        })
+        .map(str::to_string);
+    let name = match name {
+        Some(n) => n,
+        None => return Ok(None),
+    };
+
+    let mut source = String::new();
+    archive.by_name(&name)?.read_to_string(&mut source)?;
+    Ok(Some(source))
+}
+
+/// Extract setting prototypes from the `data:extend` tables of a
+/// settings.lua. This is a pattern match, not a Lua interpreter: settings
+/// built in loops or from variables are skipped, and a default that is not
+/// a literal is left unknown.
+pub fn parse_settings_lua(mod_name: &str, source: &str) -> Vec<SettingPrototype> {
+    let source = strip_comments(source);
+    let type_re = Regex::new(r#"type\s*=\s*["'](bool|int|double|string)-setting["']"#).unwrap();
+
+    let mut prototypes = Vec::new();
+    for caps in type_re.captures_iter(&source) {
+        let start = caps.get(0).unwrap().start();
+        let table = match enclosing_table(&source, start) {
+            Some(t) => t,
+            None => continue,
+        };
+        let kind = match &caps[1] {
+            "bool" => SettingKind::Bool,
+            "int" => SettingKind::Int,
+            "double" => SettingKind::Double,
+            _ => SettingKind::String,
+        };
+        if let Some(proto) = parse_prototype(mod_name, kind, table) {
+            prototypes.push(proto);
+        }
+    }
+    prototypes
+}
+
+fn parse_prototype(mod_name: &str, kind: SettingKind, table: &str) -> Option<SettingPrototype> {
+    let allowed_re = Regex::new(r"allowed_values\s*=\s*\{([^}]*)\}").unwrap();
+    let allowed_values: Vec<String> = allowed_re
+        .captures(table)
+        .map(|caps| {
+            caps[1]
+                .split(',')
+                .map(|v| v.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Only look at the fields of this table, not of nested ones
+    let flat = flatten_nested(table);
+    let field = |key: &str| {
+        let re = Regex::new(&format!(
+            r#"(?:^|[,{{\s]){}\s*=\s*("[^"]*"|'[^']*'|[^,}}\s]+)"#,
+            key
+        ))
+        .unwrap();
+        re.captures(&flat).map(|c| c[1].to_string())
+    };
+    let string_field = |key: &str| {
+        field(key).and_then(|v| {
+            let unquoted = v.trim_matches(|c| c == '"' || c == '\'');
+            (unquoted.len() + 2 == v.len()).then(|| unquoted.to_string())
+        })
+    };
+
+    let name = string_field("name")?;
+    let scope = SettingScope::from_key(&string_field("setting_type")?)?;
+    let default = field("default_value").and_then(|raw| match kind {
+        SettingKind::Bool => raw.parse().ok().map(SettingValue::Bool),
+        SettingKind::Int => raw.parse().ok().map(SettingValue::Int),
+        SettingKind::Double => raw.parse().ok().map(SettingValue::Double),
+        SettingKind::String => string_field("default_value").map(SettingValue::String),
+    });
+
+    Some(SettingPrototype {
+        mod_name: mod_name.to_string(),
+        name,
+        kind,
+        scope,
+        default,
+        minimum: field("minimum_value").and_then(|v| v.parse().ok()),
+        maximum: field("maximum_value").and_then(|v| v.parse().ok()),
+        allowed_values,
+        order: string_field("order").unwrap_or_default(),
+    })
+}
+
+fn strip_comments(source: &str) -> String {
+    let block = Regex::new(r"(?s)--\[=*\[.*?\]=*\]").unwrap();
+    let line = Regex::new(r"--[^\n]*").unwrap();
+    let without_blocks = block.replace_all(source, "");
+    line.replace_all(&without_blocks, "").into_owned()
+}
+
+/// The innermost `{ ... }` around byte offset `pos`.
+fn enclosing_table(source: &str, pos: usize) -> Option<&str> {
+    let bytes = source.as_bytes();
+
+    let mut depth = 0;
+    let mut start = None;
+    for i in (0..pos).rev() {
+        match bytes[i] {
+            b'}' => depth += 1,
+            b'{' if depth == 0 => {
+                start = Some(i);
+                break;
+            }
+            b'{' => depth -= 1,
+            _ => {}
+        }
+    }
+    let start = start?;
+
+    let mut depth = 0;
+    for (i, &b) in bytes.iter().enumerate().skip(start) {
+        match b {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&source[start..=i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Blank out every table nested inside the outer one.
+fn flatten_nested(table: &str) -> String {
+    let mut out = String::with_capacity(table.len());
+    let mut depth = 0;
+    for c in table.chars() {
+        match c {
+            '{' => {
+                depth += 1;
+                if depth == 1 {
+                    out.push(c);
+                }
+            }
+            '}' => {
+                if depth == 1 {
+                    out.push(c);
+                }
+                depth -= 1;
+            }
+            _ if depth <= 1 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const SETTINGS_LUA: &str = r#"
+-- Startup settings
+data:extend({
+    {
+        type = "bool-setting",
+        name = "mymod-enable-cheats",
+        setting_type = "startup",
+        default_value = false,
+        order = "b",
+    },
+    {
+        type = "int-setting",
+        name = "mymod-stack-size",
+        setting_type = "startup",
+        default_value = 100,
+        minimum_value = 1,
+        maximum_value = 1000,
+        order = "a",
+    },
+    --[[ {
+        type = "bool-setting",
+        name = "mymod-commented-out",
+        setting_type = "startup",
+        default_value = true,
+    }, ]]
+    {
+        type = "string-setting",
+        name = "mymod-mode",
+        setting_type = "runtime-global",
+        default_value = "normal",
+        allowed_values = {"easy", "normal", "hard"},
+    },
+    {
+        type = 'double-setting',
+        name = 'mymod-rate',
+        setting_type = 'runtime-per-user',
+        default_value = some_variable,
+    },
+})
+"#;
+
+    fn protos() -> Vec<SettingPrototype> {
+        parse_settings_lua("mymod", SETTINGS_LUA)
+    }
+
+    #[test]
+    fn parses_settings_lua() {
+        let protos = protos();
+        let names: Vec<&str> = protos.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["mymod-enable-cheats", "mymod-stack-size", "mymod-mode", "mymod-rate"]
+        );
+
+        assert_eq!(protos[0].default, Some(SettingValue::Bool(false)));
+        assert_eq!(protos[1].kind, SettingKind::Int);
+        assert_eq!(protos[1].default, Some(SettingValue::Int(100)));
+        assert_eq!(protos[1].maximum, Some(1000.0));
+        assert_eq!(protos[1].order, "a");
+        assert_eq!(protos[2].scope, SettingScope::RuntimeGlobal);
+        assert_eq!(protos[2].allowed_values, vec!["easy", "normal", "hard"]);
+        assert_eq!(
+            protos[2].default,
+            Some(SettingValue::String("normal".to_string()))
+        );
+        assert_eq!(protos[3].scope, SettingScope::RuntimePerUser);
+        assert_eq!(protos[3].default, None);
+    }
+
+    #[test]
+    fn validates_input() {
+        let protos = protos();
+        assert_eq!(protos[0].parse_value("true").unwrap(), SettingValue::Bool(true));
+        assert!(protos[0].parse_value("yes").is_err());
+        assert_eq!(protos[1].parse_value(" 42 ").unwrap(), SettingValue::Int(42));
+        assert!(protos[1].parse_value("4.5").is_err());
+        assert!(protos[1].parse_value("0").is_err());
+        assert!(protos[1].parse_value("1001").is_err());
+        assert!(protos[2].parse_value("hard").is_ok());
+        assert!(protos[2].parse_value("insane").is_err());
+    }
+
+    #[test]
+    fn stores_and_reloads_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+        let protos = protos();
+
+        let mut settings = ModSettings::load_or_create(mods_dir, "1.1.110").unwrap();
+        assert_eq!(settings.version, [1, 1, 110, 0]);
+        assert_eq!(settings.value(&protos[1]), None);
+
+        settings.set_value(&protos[0], &SettingValue::Bool(true));
+        settings.set_value(&protos[1], &SettingValue::Int(250));
+        settings.set_value(&protos[2], &SettingValue::String("hard".to_string()));
+        settings.save(mods_dir).unwrap();
+
+        let mut reloaded = ModSettings::load_or_create(mods_dir, "2.0").unwrap();
+        assert_eq!(reloaded, settings);
+        assert_eq!(reloaded.value(&protos[0]), Some(SettingValue::Bool(true)));
+        assert_eq!(reloaded.value(&protos[1]), Some(SettingValue::Int(250)));
+        // 1.1 stores ints as doubles
+        let stack_size = reloaded.tree.get("startup").unwrap().get("mymod-stack-size").unwrap();
+        assert_eq!(
+            stack_size.get("value").map(|v| &v.value),
+            Some(&Value::Number(250.0))
+        );
+
+        reloaded.reset(&protos[1]);
+        assert_eq!(reloaded.value(&protos[1]), None);
+        assert_eq!(reloaded.value(&protos[0]), Some(SettingValue::Bool(true)));
+    }
+
+    #[test]
+    fn round_trips_a_settings_file() {
+        // Factorio 1.1.110: { startup = { ["mymod-enable-cheats"] = { value = true } },
+        // ["runtime-per-user"] = { ["mymod-rate"] = { value = 0.5 } } },
+        // with the any-type flag set on the root
+        let mut data = vec![1, 0, 1, 0, 110, 0, 0, 0, 0];
+        data.extend_from_slice(&[5, 1, 2, 0, 0, 0]);
+        data.extend_from_slice(&[0, 7]);
+        data.extend_from_slice(b"startup");
+        data.extend_from_slice(&[5, 0, 1, 0, 0, 0, 0, 19]);
+        data.extend_from_slice(b"mymod-enable-cheats");
+        data.extend_from_slice(&[5, 0, 1, 0, 0, 0, 0, 5]);
+        data.extend_from_slice(b"value");
+        data.extend_from_slice(&[1, 0, 1]);
+        data.extend_from_slice(&[0, 16]);
+        data.extend_from_slice(b"runtime-per-user");
+        data.extend_from_slice(&[5, 0, 1, 0, 0, 0, 0, 10]);
+        data.extend_from_slice(b"mymod-rate");
+        data.extend_from_slice(&[5, 0, 1, 0, 0, 0, 0, 5]);
+        data.extend_from_slice(b"value");
+        data.extend_from_slice(&[2, 0]);
+        data.extend_from_slice(&0.5f64.to_le_bytes());
+
+        let settings = ModSettings::parse(&data).unwrap();
+        assert!(settings.tree.any_type);
+        let protos = protos();
+        assert_eq!(settings.value(&protos[0]), Some(SettingValue::Bool(true)));
+        assert_eq!(settings.value(&protos[3]), Some(SettingValue::Double(0.5)));
+        assert_eq!(settings.to_bytes(), data);
+    }
+
+    #[test]
+    fn reads_prototypes_from_mod_zips() {
+        let dir = tempfile::tempdir().unwrap();
+        let mods_dir = dir.path().to_str().unwrap();
+
+        let zip_path =
+            crate::factorio::test_support::write_mod_zip(dir.path(), "mymod", "1.0.0", &[]);
+        let mut zip = zip::ZipWriter::new_append(
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&zip_path)
+                .unwrap(),
+        )
+        .unwrap();
+        zip.start_file(
+            "mymod_1.0.0/settings.lua",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(SETTINGS_LUA.as_bytes()).unwrap();
+        zip.finish().unwrap();
+        crate::factorio::test_support::write_mod_zip(dir.path(), "plain", "1.0.0", &[]);
+
+        let installed = crate::factorio::installed::read_installed_mods(mods_dir).unwrap();
+        let protos = read_prototypes(mods_dir, &installed, |w| panic!("{}", w));
+        assert_eq!(protos.len(), 4);
+        // Sorted by scope, then order
+        assert_eq!(protos[0].name, "mymod-stack-size");
+        assert_eq!(protos[1].name, "mymod-enable-cheats");
+    }
+}
@@ -0,0 +1,111 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::installed::InstalledMod;
+use super::types::Mod;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    UpToDate,
+    Outdated,
+    /// The portal has the mod, but no release for the server's Factorio version.
+    NoCompatibleRelease,
+    NotOnPortal,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutdatedEntry {
+    pub name: String,
+    pub installed: semver::Version,
+    /// Newest release for the server's Factorio version, if any.
+    pub latest: Option<semver::Version>,
+    pub status: Status,
+}
+
+/// Compare installed mods against their portal releases for `factorio_version`.
+pub fn check(
+    installed: &[InstalledMod],
+    portal_mods: &[Mod],
+    factorio_version: &str,
+) -> Vec<OutdatedEntry> {
+    let by_name: HashMap<&str, &Mod> = portal_mods.iter().map(|m| (m.name.as_str(), m)).collect();
+
+    let mut entries: Vec<OutdatedEntry> = installed
+        .iter()
+        .map(|m| {
+            let latest = by_name.get(m.name.as_str()).map(|portal| {
+                portal
+                    .releases
+                    .iter()
+                    .filter(|r| r.factorio_version == factorio_version)
+                    .map(|r| r.version.clone())
+                    .max()
+            });
+
+            let (latest, status) = match latest {
+                None => (None, Status::NotOnPortal),
+                Some(None) => (None, Status::NoCompatibleRelease),
+                Some(Some(v)) if v > m.version => (Some(v), Status::Outdated),
+                Some(Some(v)) => (Some(v), Status::UpToDate),
+            };
+
+            OutdatedEntry {
+                name: m.name.clone(),
+                installed: m.version.clone(),
+                latest,
+                status,
+            }
+        })
+        .collect();
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::test_support::{make_installed, make_mod, make_release};
+
+    #[test]
+    fn classifies_each_mod() {
+        let installed = vec![
+            make_installed("current", "1.0.0", &[]),
+            make_installed("stale", "1.0.0", &[]),
+            make_installed("ported", "1.0.0", &[]),
+            make_installed("private", "1.0.0", &[]),
+        ];
+        let portal = vec![
+            make_mod(
+                "current",
+                vec![
+                    make_release("current", "1.0.0", "1.1", &[]),
+                    make_release("current", "2.0.0", "2.0", &[]),
+                ],
+            ),
+            make_mod(
+                "stale",
+                vec![
+                    make_release("stale", "1.0.0", "1.1", &[]),
+                    make_release("stale", "1.2.0", "1.1", &[]),
+                ],
+            ),
+            make_mod("ported", vec![make_release("ported", "2.0.0", "2.0", &[])]),
+        ];
+
+        let entries = check(&installed, &portal, "1.1");
+        let status: Vec<(&str, Status)> =
+            entries.iter().map(|e| (e.name.as_str(), e.status)).collect();
+        assert_eq!(
+            status,
+            vec![
+                ("current", Status::UpToDate),
+                ("ported", Status::NoCompatibleRelease),
+                ("private", Status::NotOnPortal),
+                ("stale", Status::Outdated),
+            ]
+        );
+        assert_eq!(entries[3].latest, Some(semver::Version::new(1, 2, 0)));
+    }
+}
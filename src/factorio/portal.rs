@@ -0,0 +1,79 @@
+use anyhow::Result;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use super::api::{Progress, DEFAULT_CONCURRENCY};
+use super::credentials::Credentials;
+use super::types::{Mod, ModListEntry, Release};
+
+/// [`ModPortal::fetch_mod_details`] error for a mod the portal does not have,
+/// as opposed to a portal that could not be asked.
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mod '{}' not found on the mod portal", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// A boxed future, so [`ModPortal`] can be used as a trait object.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A mod portal that can be handed to spawned tasks.
+pub type SharedPortal = Arc<dyn ModPortal>;
+
+/// Callback receiving download progress.
+pub type Reporter = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Everything FML asks of the mod portal.
+///
+/// [`super::api::Portal`] talks to the real portal over HTTP; tests use the
+/// fixture-directory and in-memory portals in `fake_portal`.
+pub trait ModPortal: Send + Sync {
+    /// Mods with a release for `factorio_version`, most downloaded first.
+    fn fetch_mod_list<'a>(
+        &'a self,
+        factorio_version: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ModListEntry>>>;
+
+    /// A mod with all of its releases and their dependencies.
+    fn fetch_mod_details<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Mod>>;
+
+    /// Several mods at once. Releases carry no dependencies, and unknown
+    /// mods are missing from the result.
+    fn fetch_mods_by_name<'a>(&'a self, names: &'a [String]) -> BoxFuture<'a, Result<Vec<Mod>>>;
+
+    /// Download `release` into `dir`, verified against its SHA-1.
+    fn download_mod<'a>(
+        &'a self,
+        release: &'a Release,
+        credentials: &'a Credentials,
+        dir: &'a str,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Whether the portal accepts `credentials` for its bookmarks.
+    fn validate_credentials<'a>(&'a self, credentials: &'a Credentials)
+        -> BoxFuture<'a, Result<bool>>;
+
+    /// This portal, reporting the progress of every download to `report`.
+    fn with_reporter(&self, report: Reporter) -> SharedPortal;
+
+    /// A mod list available without waiting for the portal, if any.
+    fn cached_mod_list(&self, _factorio_version: &str) -> Option<Vec<ModListEntry>> {
+        None
+    }
+
+    /// How many downloads may run at once.
+    fn concurrency(&self) -> usize {
+        DEFAULT_CONCURRENCY
+    }
+
+    fn is_offline(&self) -> bool {
+        false
+    }
+}
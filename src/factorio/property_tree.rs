@@ -0,0 +1,322 @@
+use anyhow::{bail, Result};
+
+/// A node of Factorio's binary property tree, as used by `mod-settings.dat`.
+///
+/// Nodes keep the "any type" flag they were read with and dictionaries keep
+/// their on-disk order, so a file that is read and written back unchanged
+/// stays byte-identical.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyTree {
+    pub value: Value,
+    /// Stored after the type of every node. Factorio writes it as false.
+    pub any_type: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    None,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<PropertyTree>),
+    Dictionary(Vec<(String, PropertyTree)>),
+    /// Added in Factorio 2.0.
+    SignedInteger(i64),
+    /// Added in Factorio 2.0.
+    UnsignedInteger(u64),
+}
+
+impl From<Value> for PropertyTree {
+    fn from(value: Value) -> Self {
+        PropertyTree {
+            value,
+            any_type: false,
+        }
+    }
+}
+
+impl PropertyTree {
+    pub fn get(&self, key: &str) -> Option<&PropertyTree> {
+        match &self.value {
+            Value::Dictionary(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Entry `key` of a dictionary, inserted as `default` when missing.
+    /// Anything that is not a dictionary is replaced by an empty one first.
+    pub fn entry(&mut self, key: &str, default: Value) -> &mut PropertyTree {
+        if !matches!(self.value, Value::Dictionary(_)) {
+            self.value = Value::Dictionary(Vec::new());
+        }
+        let Value::Dictionary(entries) = &mut self.value else {
+            unreachable!()
+        };
+        let pos = match entries.iter().position(|(k, _)| k == key) {
+            Some(pos) => pos,
+            None => {
+                entries.push((key.to_string(), default.into()));
+                entries.len() - 1
+            }
+        };
+        &mut entries[pos].1
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<PropertyTree> {
+        match &mut self.value {
+            Value::Dictionary(entries) => {
+                let pos = entries.iter().position(|(k, _)| k == key)?;
+                Some(entries.remove(pos).1)
+            }
+            _ => None,
+        }
+    }
+}
+
+// --- Reading ---
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            bail!("Unexpected end of data at byte {}", self.pos);
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    /// One byte, or 0xFF followed by the full u32.
+    fn optimized_u32(&mut self) -> Result<u32> {
+        match self.u8()? {
+            0xFF => self.u32(),
+            b => Ok(b as u32),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        // Leading flag: the string is empty
+        if self.u8()? != 0 {
+            return Ok(String::new());
+        }
+        let len = self.optimized_u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    pub fn tree(&mut self) -> Result<PropertyTree> {
+        let kind = self.u8()?;
+        let any_type = self.u8()? != 0;
+        let value = match kind {
+            0 => Value::None,
+            1 => Value::Bool(self.u8()? != 0),
+            2 => Value::Number(f64::from_bits(self.u64()?)),
+            3 => Value::String(self.string()?),
+            4 => {
+                let count = self.u32()?;
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    // List items carry an (unused) key as well
+                    self.string()?;
+                    items.push(self.tree()?);
+                }
+                Value::List(items)
+            }
+            5 => {
+                let count = self.u32()?;
+                let mut entries = Vec::new();
+                for _ in 0..count {
+                    let key = self.string()?;
+                    entries.push((key, self.tree()?));
+                }
+                Value::Dictionary(entries)
+            }
+            6 => Value::SignedInteger(self.u64()? as i64),
+            7 => Value::UnsignedInteger(self.u64()?),
+            other => bail!("Unknown property tree type {} at byte {}", other, self.pos - 2),
+        };
+        Ok(PropertyTree { value, any_type })
+    }
+}
+
+// --- Writing ---
+
+fn write_optimized_u32(out: &mut Vec<u8>, value: u32) {
+    if value < 0xFF {
+        out.push(value as u8);
+    } else {
+        out.push(0xFF);
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    if value.is_empty() {
+        out.push(1);
+        return;
+    }
+    out.push(0);
+    write_optimized_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+pub fn write_tree(out: &mut Vec<u8>, tree: &PropertyTree) {
+    let kind = match &tree.value {
+        Value::None => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::List(_) => 4,
+        Value::Dictionary(_) => 5,
+        Value::SignedInteger(_) => 6,
+        Value::UnsignedInteger(_) => 7,
+    };
+    out.push(kind);
+    out.push(tree.any_type as u8);
+
+    match &tree.value {
+        Value::None => {}
+        Value::Bool(b) => out.push(*b as u8),
+        Value::Number(n) => out.extend_from_slice(&n.to_bits().to_le_bytes()),
+        Value::String(s) => write_string(out, s),
+        Value::List(items) => {
+            out.extend_from_slice(&(items.len() as u32).to_le_bytes());
+            for item in items {
+                write_string(out, "");
+                write_tree(out, item);
+            }
+        }
+        Value::Dictionary(entries) => {
+            out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+            for (key, value) in entries {
+                write_string(out, key);
+                write_tree(out, value);
+            }
+        }
+        Value::SignedInteger(i) => out.extend_from_slice(&i.to_le_bytes()),
+        Value::UnsignedInteger(u) => out.extend_from_slice(&u.to_le_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(tree: Option<&PropertyTree>) -> Option<&Value> {
+        tree.map(|t| &t.value)
+    }
+
+    #[test]
+    fn reads_known_bytes() {
+        // { "flag": true, "name": "abc" }
+        let data = [
+            5, 0, 2, 0, 0, 0, //
+            0, 4, b'f', b'l', b'a', b'g', 1, 0, 1, //
+            0, 4, b'n', b'a', b'm', b'e', 3, 0, 0, 3, b'a', b'b', b'c',
+        ];
+        let tree = Reader::new(&data).tree().unwrap();
+        assert_eq!(value(tree.get("flag")), Some(&Value::Bool(true)));
+        assert_eq!(
+            value(tree.get("name")),
+            Some(&Value::String("abc".to_string()))
+        );
+
+        let mut out = Vec::new();
+        write_tree(&mut out, &tree);
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn keeps_the_any_type_flag() {
+        // { "flag": true } with the flag set on both nodes
+        let data = [
+            5, 1, 1, 0, 0, 0, //
+            0, 4, b'f', b'l', b'a', b'g', 1, 1, 1,
+        ];
+        let tree = Reader::new(&data).tree().unwrap();
+        assert!(tree.any_type);
+        assert!(tree.get("flag").unwrap().any_type);
+
+        let mut out = Vec::new();
+        write_tree(&mut out, &tree);
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        let long = "x".repeat(300);
+        let tree: PropertyTree = Value::Dictionary(vec![
+            ("none".to_string(), Value::None.into()),
+            ("number".to_string(), Value::Number(-1.5).into()),
+            ("empty".to_string(), Value::String(String::new()).into()),
+            ("long".to_string(), Value::String(long).into()),
+            (
+                "list".to_string(),
+                Value::List(vec![Value::Bool(false).into(), Value::Number(2.0).into()]).into(),
+            ),
+            ("signed".to_string(), Value::SignedInteger(-42).into()),
+            (
+                "unsigned".to_string(),
+                Value::UnsignedInteger(u64::MAX).into(),
+            ),
+        ])
+        .into();
+
+        let mut out = Vec::new();
+        write_tree(&mut out, &tree);
+        let mut reader = Reader::new(&out);
+        assert_eq!(reader.tree().unwrap(), tree);
+        assert!(reader.is_at_end());
+    }
+
+    #[test]
+    fn entry_inserts_and_keeps_order() {
+        let mut tree = PropertyTree::from(Value::None);
+        tree.entry("b", Value::None).value = Value::Bool(true);
+        tree.entry("a", Value::Number(1.0));
+        tree.entry("b", Value::None).value = Value::Bool(false);
+
+        let Value::Dictionary(entries) = &tree.value else {
+            panic!("expected a dictionary");
+        };
+        let keys: Vec<&str> = entries.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["b", "a"]);
+        assert_eq!(value(tree.get("b")), Some(&Value::Bool(false)));
+        assert!(tree.remove("a").is_some());
+        assert!(tree.get("a").is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_type() {
+        assert!(Reader::new(&[9, 0]).tree().is_err());
+        assert!(Reader::new(&[2, 0, 1]).tree().is_err());
+    }
+}
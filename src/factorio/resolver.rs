@@ -1,8 +1,16 @@
 use anyhow::{bail, Result};
-use std::collections::{HashMap, HashSet};
-use std::future::Future;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::task::Poll;
 
-use super::types::{DependencyType, Mod, Release};
+use super::installed::InstalledMod;
+use super::portal::{BoxFuture, ModPortal, NotFound};
+use super::types::{self, DependencyType, Mod, Release};
+
+/// Give up after picking this many releases in one search.
+const MAX_STEPS: usize = 100_000;
+
+/// Longest explanation printed for a failed resolution.
+const MAX_EXPLANATION_LINES: usize = 40;
 
 #[derive(Debug)]
 pub struct ResolveResult {
@@ -11,205 +19,558 @@ pub struct ResolveResult {
 
 /// Resolve all dependencies for a mod before downloading anything.
 ///
-/// The `fetch_fn` parameter makes this testable without hitting the network.
-/// It takes a mod name and returns the full Mod details.
-pub async fn resolve<F, Fut>(
+/// `version_req` constrains the release picked for `mod_name` itself.
+/// Mod details come from `portal`, all fetched before the search starts.
+/// `enabled` are the installed mods Factorio loads: releases their `!`
+/// dependencies name are ruled out. Only those still in `installed` with the
+/// same version count, so a mod being replaced does not block anything.
+///
+/// Every release for `factorio_version` is a candidate, newest first. When a
+/// pick leads to a conflict (a version requirement, an installed mod that
+/// does not match, or an incompatibility) the search backtracks to the pick
+/// that caused it and tries an older release there. Installed mods keep
+/// their version. If nothing works, the error explains why each candidate
+/// was ruled out.
+pub async fn resolve(
     mod_name: &str,
+    version_req: &semver::VersionReq,
     factorio_version: &str,
     installed: &HashMap<String, semver::Version>,
-    fetch_fn: &F,
-) -> Result<ResolveResult>
-where
-    F: Fn(String) -> Fut + Send + Sync,
-    Fut: Future<Output = Result<Mod>> + Send,
-{
-    let mut to_download: Vec<(String, Release)> = Vec::new();
-    let mut visited: HashSet<String> = HashSet::new();
-
-    resolve_recursive(
-        mod_name,
-        &semver::VersionReq::STAR,
-        factorio_version,
-        installed,
-        fetch_fn,
-        &mut to_download,
-        &mut visited,
-        mod_name, // root mod for error messages
-    )
-    .await?;
-
-    Ok(ResolveResult { to_download })
-}
+    enabled: &[InstalledMod],
+    portal: &dyn ModPortal,
+) -> Result<ResolveResult> {
+    // Skip base and the expansion mods — they ship with the game
+    if types::is_builtin_mod(mod_name) {
+        return Ok(ResolveResult {
+            to_download: Vec::new(),
+        });
+    }
 
-#[allow(clippy::too_many_arguments)]
-fn resolve_recursive<'a, F, Fut>(
-    mod_name: &'a str,
-    version_req: &'a semver::VersionReq,
-    factorio_version: &'a str,
-    installed: &'a HashMap<String, semver::Version>,
-    fetch_fn: &'a F,
-    to_download: &'a mut Vec<(String, Release)>,
-    visited: &'a mut HashSet<String>,
-    root_mod: &'a str,
-) -> std::pin::Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>
-where
-    F: Fn(String) -> Fut + Send + Sync,
-    Fut: Future<Output = Result<Mod>> + Send + 'a,
-{
-    Box::pin(async move {
-    // Skip base — it's the game itself
-    if mod_name == "base" {
-        return Ok(());
-    }
-
-    // Skip if already visited (cycle/duplicate detection)
-    if visited.contains(mod_name) {
-        return Ok(());
-    }
-    visited.insert(mod_name.to_string());
-
-    // Check if already installed
     if let Some(installed_version) = installed.get(mod_name) {
         if version_req.matches(installed_version) {
-            return Ok(());
-        } else {
-            bail!(
-                "Installed version {} of mod '{}' does not satisfy required {}. \
-                 Remove it first and retry.",
-                installed_version,
-                mod_name,
-                version_req
-            );
+            return Ok(ResolveResult {
+                to_download: Vec::new(),
+            });
         }
+        bail!(
+            "Installed version {} of mod '{}' does not satisfy required {}. \
+             Update or remove it first and retry.",
+            installed_version,
+            mod_name,
+            version_req
+        );
     }
 
-    // Fetch mod details
-    let mod_details = fetch_fn(mod_name.to_string()).await.map_err(|e| {
-        anyhow::anyhow!(
-            "Failed to fetch dependency '{}' (needed by '{}'): {}",
-            mod_name,
-            root_mod,
-            e
-        )
-    })?;
-
-    // Find best release: latest that matches factorio_version and version constraint.
-    // Releases are iterated newest-first (reverse order).
-    let release = mod_details
-        .releases
+    let (releases, mut failed) = prefetch(mod_name, factorio_version, installed, portal).await;
+    let missing = failed
         .iter()
-        .rev()
-        .find(|r| {
-            r.factorio_version == factorio_version && version_req.matches(&r.version)
-        })
-        .cloned();
+        .filter(|(_, e)| e.downcast_ref::<NotFound>().is_some())
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut solver = Solver {
+        root: mod_name,
+        root_req: version_req,
+        installed,
+        enabled,
+        releases,
+        missing,
+        steps: 0,
+    };
 
-    let release = match release {
-        Some(r) => r,
-        None => {
+    let mut picked = Vec::new();
+    match solver.search(&mut picked) {
+        Ok(()) => Ok(ResolveResult {
+            to_download: dependency_order(mod_name, &picked),
+        }),
+        Err(Stop::Unavailable(name)) => {
+            let e = failed
+                .remove(&name)
+                .unwrap_or_else(|| anyhow::anyhow!("Mod '{}' was not fetched", name));
+            bail!(
+                "Failed to fetch dependency '{}' (needed by '{}'): {}",
+                name,
+                mod_name,
+                e
+            );
+        }
+        Err(Stop::Conflict(conflict)) => {
             bail!(
-                "No compatible release found for mod '{}' \
-                 (need Factorio version {}, version {})",
+                "No compatible release found for mod '{}' (need Factorio version {}, version {}):\n{}",
                 mod_name,
                 factorio_version,
-                version_req
+                version_req,
+                conflict.explain(mod_name, factorio_version)
             );
         }
-    };
+        Err(Stop::GaveUp) => {
+            bail!(
+                "Gave up resolving the dependencies of '{}' after trying {} releases",
+                mod_name,
+                MAX_STEPS
+            );
+        }
+    }
+}
+
+/// Releases for the Factorio version, newest first, and how many releases
+/// the mod has in total, by mod name.
+type Candidates = HashMap<String, (Vec<Release>, usize)>;
+
+/// Fetch the details of `root` and of every mod its releases for
+/// `factorio_version` require, transitively, up to the portal's concurrency
+/// limit at a time. Installed mods and the game's own are not followed.
+///
+/// Mods that cannot be fetched are returned with their error, which only
+/// matters if the search gets to them: a release that requires a mod gone
+/// from the portal is passed over for an older one.
+async fn prefetch(
+    root: &str,
+    factorio_version: &str,
+    installed: &HashMap<String, semver::Version>,
+    portal: &dyn ModPortal,
+) -> (Candidates, HashMap<String, anyhow::Error>) {
+    let mut releases = HashMap::new();
+    let mut failed = HashMap::new();
+    let mut seen = HashSet::from([root.to_string()]);
+    let mut pending = vec![root.to_string()];
+    let mut running: Vec<BoxFuture<'_, (String, Result<Mod>)>> = Vec::new();
+
+    loop {
+        while running.len() < portal.concurrency() {
+            let Some(name) = pending.pop() else {
+                break;
+            };
+            running.push(Box::pin(async move {
+                let result = portal.fetch_mod_details(&name).await;
+                (name, result)
+            }));
+        }
+        if running.is_empty() {
+            break;
+        }
+
+        let (name, result) = std::future::poll_fn(|cx| {
+            for i in 0..running.len() {
+                if let Poll::Ready(fetched) = running[i].as_mut().poll(cx) {
+                    drop(running.swap_remove(i));
+                    return Poll::Ready(fetched);
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+
+        let details = match result {
+            Ok(details) => details,
+            Err(e) => {
+                failed.insert(name, e);
+                continue;
+            }
+        };
+        let total = details.releases.len();
+        let mut candidates: Vec<Release> = details
+            .releases
+            .into_iter()
+            .filter(|r| r.factorio_version == factorio_version)
+            .collect();
+        candidates.sort_by(|a, b| b.version.cmp(&a.version));
+
+        for dep in candidates.iter().flat_map(|r| &r.dependencies) {
+            if dep.dep_type == DependencyType::Required
+                && !types::is_builtin_mod(&dep.name)
+                && !installed.contains_key(&dep.name)
+                && seen.insert(dep.name.clone())
+            {
+                pending.push(dep.name.clone());
+            }
+        }
+        releases.insert(name, (candidates, total));
+    }
+
+    (releases, failed)
+}
+
+/// `picked` in the order it has to be installed: every mod after the
+/// mods it requires.
+fn dependency_order(root: &str, picked: &[(String, Release)]) -> Vec<(String, Release)> {
+    fn visit(
+        name: &str,
+        picked: &[(String, Release)],
+        seen: &mut HashSet<String>,
+        out: &mut Vec<(String, Release)>,
+    ) {
+        let Some((_, release)) = picked.iter().find(|(n, _)| n == name) else {
+            return;
+        };
+        if !seen.insert(name.to_string()) {
+            return;
+        }
+        for dep in &release.dependencies {
+            if dep.dep_type == DependencyType::Required {
+                visit(&dep.name, picked, seen, out);
+            }
+        }
+        out.push((name.to_string(), release.clone()));
+    }
+
+    let mut out = Vec::new();
+    visit(root, picked, &mut HashSet::new(), &mut out);
+    out
+}
+
+// --- Search ---
+
+struct Solver<'a> {
+    root: &'a str,
+    root_req: &'a semver::VersionReq,
+    installed: &'a HashMap<String, semver::Version>,
+    enabled: &'a [InstalledMod],
+    releases: Candidates,
+    /// Mods the portal does not have.
+    missing: HashSet<String>,
+    steps: usize,
+}
+
+/// Why a search stopped without a solution.
+enum Stop {
+    /// The portal could not be asked for the details of this mod.
+    Unavailable(String),
+    Conflict(Conflict),
+    GaveUp,
+}
 
-    // Process dependencies of this release before adding it to the download list
-    // (dependency-first order)
-    for dep in &release.dependencies {
-        match dep.dep_type {
-            DependencyType::Optional => {
-                // Skip optional dependencies
+/// No release of `name` fits the mods picked so far.
+struct Conflict {
+    name: String,
+    reasons: Vec<Reason>,
+    /// Picked mods whose choice of release led here. Picking another
+    /// release of any other mod cannot help.
+    culprits: BTreeSet<String>,
+}
+
+enum Reason {
+    /// The mod has no release for the Factorio version.
+    NoReleases { total: usize },
+    /// A release ruled out by the picks so far.
+    Rejected { version: semver::Version, why: String },
+    /// Picking a release led to a conflict further down.
+    Leads { version: semver::Version, conflict: Conflict },
+}
+
+impl Solver<'_> {
+    /// Pick a release for the next mod some picked release requires, and
+    /// recurse until nothing is missing.
+    fn search(&mut self, picked: &mut Vec<(String, Release)>) -> Result<(), Stop> {
+        let Some(name) = self.next_mod(picked) else {
+            return Ok(());
+        };
+        let Some((candidates, total)) = self.releases.get(&name) else {
+            return Err(Stop::Unavailable(name));
+        };
+        let candidates = candidates.clone();
+        let total = *total;
+
+        let mut culprits = self.requirers(&name, picked);
+        let mut reasons = Vec::new();
+        if candidates.is_empty() {
+            reasons.push(Reason::NoReleases { total });
+        }
+
+        for release in candidates {
+            if let Some((why, blamed)) = self.reject(&name, &release, picked) {
+                culprits.extend(blamed);
+                reasons.push(Reason::Rejected {
+                    version: release.version,
+                    why,
+                });
                 continue;
             }
-            DependencyType::Incompatible => {
-                // Check if the incompatible mod is installed or queued
-                if installed.contains_key(&dep.name) {
-                    bail!(
-                        "Cannot install '{}': it is incompatible with installed mod '{}'",
-                        mod_name,
-                        dep.name
-                    );
+
+            self.steps += 1;
+            if self.steps > MAX_STEPS {
+                return Err(Stop::GaveUp);
+            }
+            let version = release.version.clone();
+            picked.push((name.clone(), release));
+            let Err(stop) = self.search(picked) else {
+                return Ok(());
+            };
+            picked.pop();
+
+            match stop {
+                // Another release of this mod would not change the outcome
+                Stop::Conflict(conflict) if !conflict.culprits.contains(&name) => {
+                    return Err(Stop::Conflict(conflict));
+                }
+                Stop::Conflict(conflict) => {
+                    culprits.extend(conflict.culprits.iter().cloned());
+                    reasons.push(Reason::Leads { version, conflict });
                 }
-                // Also check if it's in the to_download list
-                if to_download.iter().any(|(name, _)| name == &dep.name) {
-                    bail!(
-                        "Cannot install '{}': it is incompatible with mod '{}' \
-                         (which is also being installed)",
-                        mod_name,
-                        dep.name
-                    );
+                stop => return Err(stop),
+            }
+        }
+
+        culprits.remove(&name);
+        Err(Stop::Conflict(Conflict {
+            name,
+            reasons,
+            culprits,
+        }))
+    }
+
+    /// The first mod that a picked release requires and that is neither
+    /// picked, installed nor part of the game. The root comes first.
+    fn next_mod(&self, picked: &[(String, Release)]) -> Option<String> {
+        if picked.is_empty() {
+            return Some(self.root.to_string());
+        }
+        picked
+            .iter()
+            .flat_map(|(_, release)| &release.dependencies)
+            .filter(|dep| dep.dep_type == DependencyType::Required)
+            .map(|dep| &dep.name)
+            .find(|name| {
+                !types::is_builtin_mod(name)
+                    && !self.installed.contains_key(*name)
+                    && !picked.iter().any(|(n, _)| n == *name)
+            })
+            .cloned()
+    }
+
+    /// Picked mods that require `name`.
+    fn requirers(&self, name: &str, picked: &[(String, Release)]) -> BTreeSet<String> {
+        picked
+            .iter()
+            .filter(|(_, release)| {
+                release
+                    .dependencies
+                    .iter()
+                    .any(|d| d.dep_type == DependencyType::Required && d.name == name)
+            })
+            .map(|(n, _)| n.clone())
+            .collect()
+    }
+
+    /// Why `release` of `name` cannot join `picked`, and the picked mods to
+    /// blame for it; `None` if it can.
+    fn reject(
+        &self,
+        name: &str,
+        release: &Release,
+        picked: &[(String, Release)],
+    ) -> Option<(String, Vec<String>)> {
+        if name == self.root && !self.root_req.matches(&release.version) {
+            return Some((
+                format!("does not match the requested version {}", self.root_req),
+                Vec::new(),
+            ));
+        }
+
+        // What the picked releases say about this mod
+        for (by, by_release) in picked {
+            for dep in by_release.dependencies.iter().filter(|d| d.name == name) {
+                let matches = dep.version_req.matches(&release.version);
+                match dep.dep_type {
+                    DependencyType::Required if !matches => {
+                        return Some((
+                            format!(
+                                "does not satisfy {} (required by {} {})",
+                                dep.version_req, by, by_release.version
+                            ),
+                            vec![by.clone()],
+                        ))
+                    }
+                    DependencyType::Incompatible if matches => {
+                        return Some((
+                            format!("is incompatible with {} {}", by, by_release.version),
+                            vec![by.clone()],
+                        ))
+                    }
+                    _ => {}
                 }
             }
-            DependencyType::Required => {
-                resolve_recursive(
-                    &dep.name,
-                    &dep.version_req,
-                    factorio_version,
-                    installed,
-                    fetch_fn,
-                    to_download,
-                    visited,
-                    root_mod,
-                )
-                .await?;
+        }
+
+        // What the installed mods say about this mod
+        for m in self.enabled {
+            if self.installed.get(&m.name) != Some(&m.version) {
+                continue;
+            }
+            let declared = m.dependencies.iter().any(|d| {
+                d.dep_type == DependencyType::Incompatible
+                    && d.name == name
+                    && d.version_req.matches(&release.version)
+            });
+            if declared {
+                return Some((
+                    format!("is marked incompatible by installed mod {} {}", m.name, m.version),
+                    Vec::new(),
+                ));
             }
         }
+
+        // What this release says about the installed and picked mods
+        for dep in &release.dependencies {
+            if types::is_builtin_mod(&dep.name) {
+                continue;
+            }
+            if dep.dep_type == DependencyType::Required
+                && !self.installed.contains_key(&dep.name)
+                && self.missing.contains(&dep.name)
+            {
+                return Some((
+                    format!("requires {}, which is not on the mod portal", dep.name),
+                    Vec::new(),
+                ));
+            }
+            let installed = self.installed.get(&dep.name);
+            let other = picked.iter().find(|(n, _)| n == &dep.name);
+            match dep.dep_type {
+                DependencyType::Optional => {}
+                DependencyType::Required => {
+                    if let Some(version) = installed {
+                        if !dep.version_req.matches(version) {
+                            return Some((
+                                format!(
+                                    "requires {} {}, but installed {} {} does not satisfy it",
+                                    dep.name, dep.version_req, dep.name, version
+                                ),
+                                Vec::new(),
+                            ));
+                        }
+                    }
+                    if let Some((other, other_release)) = other {
+                        if !dep.version_req.matches(&other_release.version) {
+                            return Some((
+                                format!(
+                                    "requires {} {}, but {} {} is being installed",
+                                    dep.name, dep.version_req, other, other_release.version
+                                ),
+                                vec![other.clone()],
+                            ));
+                        }
+                    }
+                }
+                DependencyType::Incompatible => {
+                    if installed.is_some_and(|v| dep.version_req.matches(v)) {
+                        return Some((
+                            format!("is incompatible with installed mod {}", dep.name),
+                            Vec::new(),
+                        ));
+                    }
+                    if let Some((other, other_release)) = other {
+                        if dep.version_req.matches(&other_release.version) {
+                            return Some((
+                                format!(
+                                    "is incompatible with {} {}, which is also being installed",
+                                    other, other_release.version
+                                ),
+                                vec![other.clone()],
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// --- Explanation ---
+
+impl Conflict {
+    /// The reasons as an indented list, cut off after
+    /// [`MAX_EXPLANATION_LINES`] lines.
+    fn explain(&self, root: &str, factorio_version: &str) -> String {
+        let mut lines = Vec::new();
+        if self.name == root {
+            self.explain_reasons(factorio_version, 1, &mut lines);
+        } else {
+            lines.push(format!("  - no release of {} fits:", self.name));
+            self.explain_reasons(factorio_version, 2, &mut lines);
+        }
+        if lines.len() > MAX_EXPLANATION_LINES {
+            let more = lines.len() - MAX_EXPLANATION_LINES;
+            lines.truncate(MAX_EXPLANATION_LINES);
+            lines.push(format!("  ... and {} more", more));
+        }
+        lines.join("\n")
     }
 
-    // Add this mod to the download list (after its dependencies)
-    to_download.push((mod_name.to_string(), release));
+    /// Reasons that read the same apart from the version are merged into
+    /// one line, e.g. "lib 1.0.0 to 1.4.0 does not satisfy ...".
+    fn explain_reasons(&self, factorio_version: &str, depth: usize, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        let mut rendered: Vec<(Option<&semver::Version>, String, Vec<String>)> = Vec::new();
+        for reason in &self.reasons {
+            let (version, text, nested) = match reason {
+                Reason::NoReleases { total: 0 } => {
+                    (None, format!("{} has no releases", self.name), Vec::new())
+                }
+                Reason::NoReleases { .. } => (
+                    None,
+                    format!("{} has no release for Factorio {}", self.name, factorio_version),
+                    Vec::new(),
+                ),
+                Reason::Rejected { version, why } => (Some(version), why.clone(), Vec::new()),
+                Reason::Leads { version, conflict } => {
+                    let mut nested = Vec::new();
+                    conflict.explain_reasons(factorio_version, depth + 1, &mut nested);
+                    (
+                        Some(version),
+                        format!("leaves no release of {} that fits:", conflict.name),
+                        nested,
+                    )
+                }
+            };
+            rendered.push((version, text, nested));
+        }
 
-    Ok(())
-    }) // Box::pin
+        let mut i = 0;
+        while i < rendered.len() {
+            let (version, text, nested) = &rendered[i];
+            let mut j = i + 1;
+            while j < rendered.len() && rendered[j].1 == *text && rendered[j].2 == *nested {
+                j += 1;
+            }
+            let line = match version {
+                None => text.clone(),
+                // Candidates are newest first
+                Some(newest) if j - i > 1 => {
+                    let oldest = rendered[j - 1].0.expect("releases have versions");
+                    format!("{} {} to {} {}", self.name, oldest, newest, text)
+                }
+                Some(version) => format!("{} {} {}", self.name, version, text),
+            };
+            lines.push(format!("{}- {}", indent, line));
+            lines.extend(nested.iter().cloned());
+            i = j;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::factorio::types::{Dependency, Mod, Release};
+    use crate::factorio::fake_portal::MemoryPortal;
+    use crate::factorio::test_support::{self, make_installed, make_release};
+    use crate::factorio::types::Mod;
     use std::collections::HashMap;
 
     fn make_mod(name: &str, deps: Vec<&str>, version: &str, factorio_ver: &str) -> Mod {
-        let dependencies: Vec<Dependency> = deps.iter().filter_map(|d| d.parse().ok()).collect();
-        Mod {
-            name: name.to_string(),
-            title: name.to_string(),
-            summary: String::new(),
-            downloads_count: 0,
-            releases: vec![Release {
-                download_url: format!("/download/{}", name),
-                file_name: format!("{}_{}.zip", name, version),
-                version: semver::Version::parse(version).unwrap(),
-                factorio_version: factorio_ver.to_string(),
-                sha1: String::new(),
-                dependencies,
-            }],
-        }
+        test_support::make_mod(name, vec![make_release(name, version, factorio_ver, &deps)])
     }
 
-    fn make_registry(mods: Vec<Mod>) -> HashMap<String, Mod> {
-        mods.into_iter().map(|m| (m.name.clone(), m)).collect()
+    fn make_registry(mods: Vec<Mod>) -> MemoryPortal {
+        mods.into_iter().fold(MemoryPortal::new(), MemoryPortal::with_mod)
     }
 
     async fn run_resolve(
         mod_name: &str,
-        registry: &HashMap<String, Mod>,
+        registry: &MemoryPortal,
         installed: &HashMap<String, semver::Version>,
     ) -> Result<ResolveResult> {
-        let fetch = |name: String| {
-            let registry = registry.clone();
-            async move {
-                registry
-                    .get(&name)
-                    .cloned()
-                    .ok_or_else(|| anyhow::anyhow!("Mod '{}' not found", name))
-            }
-        };
-        resolve(mod_name, "1.1", installed, &fetch).await
+        resolve(mod_name, &semver::VersionReq::STAR, "1.1", installed, &[], registry).await
     }
 
     #[tokio::test]
@@ -220,6 +581,20 @@ mod tests {
         assert!(result.to_download.is_empty());
     }
 
+    #[tokio::test]
+    async fn expansion_dependencies_are_skipped() {
+        let registry = make_registry(vec![make_mod(
+            "mod-a",
+            vec!["base >= 2.0.0", "space-age", "quality"],
+            "1.0.0",
+            "1.1",
+        )]);
+        let installed = HashMap::new();
+        let result = run_resolve("mod-a", &registry, &installed).await.unwrap();
+        assert_eq!(result.to_download.len(), 1);
+        assert_eq!(result.to_download[0].0, "mod-a");
+    }
+
     #[tokio::test]
     async fn cycle_detection() {
         let registry = make_registry(vec![
@@ -342,4 +717,189 @@ mod tests {
         // mod-c should come first (deepest dependency), then mod-b, then mod-a
         assert_eq!(names, vec!["mod-c", "mod-b", "mod-a"]);
     }
+
+    #[tokio::test]
+    async fn root_version_req_respected() {
+        let mut mod_a = make_mod("mod-a", vec![], "1.0.0", "1.1");
+        mod_a.releases.push(Release {
+            download_url: "/download/mod-a".to_string(),
+            file_name: "mod-a_1.1.0.zip".to_string(),
+            version: semver::Version::new(1, 1, 0),
+            factorio_version: "1.1".to_string(),
+            sha1: String::new(),
+            dependencies: vec![],
+        });
+        let registry = make_registry(vec![mod_a]);
+        let installed = HashMap::new();
+
+        let req = semver::VersionReq::parse("=1.0.0").unwrap();
+        let result = resolve("mod-a", &req, "1.1", &installed, &[], &registry).await.unwrap();
+        assert_eq!(result.to_download.len(), 1);
+        assert_eq!(result.to_download[0].1.version, semver::Version::new(1, 0, 0));
+    }
+
+    fn picked(result: &ResolveResult) -> Vec<String> {
+        result
+            .to_download
+            .iter()
+            .map(|(name, release)| format!("{} {}", name, release.version))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn backtracks_to_an_older_release() {
+        // The newest mod-a needs a lib that does not exist yet
+        let registry = MemoryPortal::new()
+            .with_release("lib", "1.0.0", &[])
+            .with_release("mod-a", "1.0.0", &["lib >= 1.0.0"])
+            .with_release("mod-a", "2.0.0", &["lib >= 2.0.0"]);
+        let result = run_resolve("mod-a", &registry, &HashMap::new()).await.unwrap();
+        assert_eq!(picked(&result), vec!["lib 1.0.0", "mod-a 1.0.0"]);
+
+        // helper, picked after lib, only works with the older lib
+        let registry = MemoryPortal::new()
+            .with_release("lib", "1.0.0", &[])
+            .with_release("lib", "2.0.0", &[])
+            .with_release("helper", "1.0.0", &["lib < 2.0.0"])
+            .with_release("mod-a", "1.0.0", &["lib >= 1.0.0", "helper"]);
+        let result = run_resolve("mod-a", &registry, &HashMap::new()).await.unwrap();
+        assert_eq!(picked(&result), vec!["lib 1.0.0", "helper 1.0.0", "mod-a 1.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn respects_installed_versions_and_incompatibilities() {
+        let registry = MemoryPortal::new()
+            .with_release("lib", "2.0.0", &[])
+            .with_release("helper", "1.0.0", &[])
+            .with_release("helper", "2.0.0", &["! other"])
+            .with_release("other", "1.0.0", &[])
+            .with_release("mod-a", "1.0.0", &["lib >= 1.0.0", "helper", "other"])
+            .with_release("mod-a", "2.0.0", &["lib >= 2.0.0", "helper", "other"]);
+        let mut installed = HashMap::new();
+        installed.insert("lib".to_string(), semver::Version::new(1, 0, 0));
+
+        let result = run_resolve("mod-a", &registry, &installed).await.unwrap();
+        assert_eq!(
+            picked(&result),
+            vec!["helper 1.0.0", "other 1.0.0", "mod-a 1.0.0"]
+        );
+    }
+
+    #[tokio::test]
+    async fn explains_why_nothing_fits() {
+        let registry = MemoryPortal::new()
+            .with_release("lib", "1.0.0", &[])
+            .with_release("lib", "1.1.0", &[])
+            .with_release("helper", "1.0.0", &["lib >= 2.0.0"])
+            .with_release("mod-a", "1.0.0", &["! bad-mod"])
+            .with_release("mod-a", "2.0.0", &["helper"]);
+        let mut installed = HashMap::new();
+        installed.insert("bad-mod".to_string(), semver::Version::new(1, 0, 0));
+
+        let err = run_resolve("mod-a", &registry, &installed).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No compatible release found for mod 'mod-a' (need Factorio version 1.1, version *):\n\
+             \x20 - mod-a 2.0.0 leaves no release of helper that fits:\n\
+             \x20   - helper 1.0.0 leaves no release of lib that fits:\n\
+             \x20     - lib 1.0.0 to 1.1.0 does not satisfy >=2.0.0 (required by helper 1.0.0)\n\
+             \x20 - mod-a 1.0.0 is incompatible with installed mod bad-mod"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_dependencies_concurrently() {
+        let registry = MemoryPortal::new()
+            .with_release("mod-a", "1.0.0", &["lib-1", "lib-2", "lib-3"])
+            .with_release("lib-1", "1.0.0", &[])
+            .with_release("lib-2", "1.0.0", &[])
+            .with_release("lib-3", "1.0.0", &[]);
+
+        let result = run_resolve("mod-a", &registry, &HashMap::new()).await.unwrap();
+        assert_eq!(result.to_download.len(), 4);
+        assert_eq!(registry.most_concurrent_fetches(), 3);
+    }
+
+    #[tokio::test]
+    async fn passes_over_releases_that_need_deleted_mods() {
+        // The newest mod-a needs a mod gone from the portal
+        let registry = MemoryPortal::new()
+            .with_release("mod-a", "1.0.0", &["lib"])
+            .with_release("mod-a", "2.0.0", &["gone"])
+            .with_release("lib", "1.0.0", &[])
+            .with_release("mod-b", "1.0.0", &["gone"]);
+
+        let result = run_resolve("mod-a", &registry, &HashMap::new()).await.unwrap();
+        assert_eq!(picked(&result), vec!["lib 1.0.0", "mod-a 1.0.0"]);
+
+        let err = run_resolve("mod-b", &registry, &HashMap::new())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("mod-b 1.0.0 requires gone, which is not on the mod portal"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn unreachable_portal_aborts_the_search() {
+        let registry = MemoryPortal::new()
+            .with_release("mod-a", "1.0.0", &["lib"])
+            .with_release("mod-a", "2.0.0", &["flaky"])
+            .with_release("lib", "1.0.0", &[])
+            .with_release("flaky", "1.0.0", &[])
+            .with_unreachable("flaky");
+
+        let err = run_resolve("mod-a", &registry, &HashMap::new())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Failed to fetch dependency 'flaky' (needed by 'mod-a')"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn installed_mods_rule_out_what_they_declare_incompatible() {
+        let old_mod = make_installed("old-mod", "1.0.0", &["! lib >= 2.0.0"]);
+        let mut installed = HashMap::new();
+        installed.insert("old-mod".to_string(), semver::Version::new(1, 0, 0));
+        let star = semver::VersionReq::STAR;
+
+        // The newest mod-a needs a lib that old-mod rules out
+        let registry = MemoryPortal::new()
+            .with_release("lib", "1.0.0", &[])
+            .with_release("lib", "2.0.0", &[])
+            .with_release("mod-a", "1.0.0", &["lib"])
+            .with_release("mod-a", "1.1.0", &["lib >= 2.0.0"]);
+        let enabled = [old_mod];
+        let result = resolve("mod-a", &star, "1.1", &installed, &enabled, &registry)
+            .await
+            .unwrap();
+        assert_eq!(picked(&result), vec!["lib 1.0.0", "mod-a 1.0.0"]);
+
+        let registry = MemoryPortal::new()
+            .with_release("lib", "2.0.0", &[])
+            .with_release("mod-a", "1.0.0", &["lib"]);
+        let err = resolve("mod-a", &star, "1.1", &installed, &enabled, &registry)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.ends_with("- lib 2.0.0 is marked incompatible by installed mod old-mod 1.0.0"),
+            "{}",
+            err
+        );
+
+        // A mod that is being replaced no longer counts
+        installed.remove("old-mod");
+        let result = resolve("mod-a", &star, "1.1", &installed, &enabled, &registry)
+            .await
+            .unwrap();
+        assert_eq!(picked(&result), vec!["lib 2.0.0", "mod-a 1.0.0"]);
+    }
 }
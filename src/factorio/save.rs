@@ -0,0 +1,333 @@
+use anyhow::{bail, Context, Result};
+use std::io::Read;
+use std::path::Path;
+
+/// A mod recorded in a save's header, in the version the save was made with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveMod {
+    pub name: String,
+    pub version: semver::Version,
+}
+
+/// Header entries that carry the mod list, in the order they are tried.
+/// Since 1.0 `level.dat` is split into compressed `level.dat0`, `level.dat1`,
+/// ... and the header is also stored on its own in `level-init.dat`.
+const HEADER_FILES: [&str; 3] = ["level-init.dat", "level.dat", "level.dat0"];
+
+/// Read the mods (including `base` and the expansions) a save was made with.
+pub fn read_save_mods(save_path: &Path) -> Result<Vec<SaveMod>> {
+    let file = std::fs::File::open(save_path)
+        .with_context(|| format!("Failed to open {}", save_path.display()))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("{} is not a zip archive", save_path.display()))?;
+
+    let entry_name = HEADER_FILES
+        .iter()
+        .find_map(|wanted| {
+            archive
+                .file_names()
+                .find(|n| n.rsplit('/').next() == Some(*wanted))
+                .map(str::to_string)
+        })
+        .with_context(|| {
+            format!(
+                "No level.dat or level-init.dat found in {}",
+                save_path.display()
+            )
+        })?;
+
+    let mut raw = Vec::new();
+    archive
+        .by_name(&entry_name)?
+        .read_to_end(&mut raw)
+        .with_context(|| format!("Failed to read {}", entry_name))?;
+
+    let header = if is_zlib(&raw) {
+        let mut inflated = Vec::new();
+        flate2::read::ZlibDecoder::new(raw.as_slice())
+            .read_to_end(&mut inflated)
+            .with_context(|| format!("Failed to decompress {}", entry_name))?;
+        inflated
+    } else {
+        raw
+    };
+
+    parse_header(&header).with_context(|| format!("Failed to parse {}", entry_name))
+}
+
+fn is_zlib(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] == 0x78 && matches!(data[1], 0x01 | 0x5e | 0x9c | 0xda)
+}
+
+// --- Header parsing ---
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            bail!("Unexpected end of header at byte {}", self.pos);
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// One byte, or 0xFF followed by the full-width value.
+    fn optimized_u16(&mut self) -> Result<u16> {
+        match self.u8()? {
+            0xFF => self.u16(),
+            b => Ok(b as u16),
+        }
+    }
+
+    fn optimized_u32(&mut self) -> Result<u32> {
+        match self.u8()? {
+            0xFF => self.u32(),
+            b => Ok(b as u32),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.optimized_u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
+
+/// Parse the map header as written since Factorio 0.17 and return its mod list.
+///
+/// There is no official description of this format. The layout is the one
+/// OpenFactorioServerManager's save reader uses: the fields do not change
+/// between 0.17, 1.1 and 2.0, which only differ in the version numbers and the
+/// mods listed (2.0 saves list the expansion mods next to `base`).
+fn parse_header(data: &[u8]) -> Result<Vec<SaveMod>> {
+    let mut r = Reader { data, pos: 0 };
+
+    let major = r.u16()?;
+    let minor = r.u16()?;
+    let _patch = r.u16()?;
+    let _build = r.u16()?;
+    if (major, minor) < (0, 17) {
+        bail!("Saves from Factorio {}.{} are not supported", major, minor);
+    }
+    r.u8()?;
+
+    let _campaign = r.string()?;
+    let _level_name = r.string()?;
+    let _base_mod = r.string()?;
+    let _difficulty = r.u8()?;
+    let _finished = r.u8()?;
+    let _player_won = r.u8()?;
+    let _next_level = r.string()?;
+    let _can_continue = r.u8()?;
+    let _finished_but_continuing = r.u8()?;
+    let _saving_replay = r.u8()?;
+    let _allow_non_admin_debug_options = r.u8()?;
+    // Version the map was loaded from: major, minor, patch and build
+    for _ in 0..3 {
+        r.optimized_u16()?;
+    }
+    r.u16()?;
+    let _allowed_commands = r.u8()?;
+
+    // No capacity up front: a corrupt count must not allocate before it fails
+    let count = r.optimized_u32()?;
+    let mut mods = Vec::new();
+    for _ in 0..count {
+        let name = r.string()?;
+        let major = r.optimized_u16()?;
+        let minor = r.optimized_u16()?;
+        let patch = r.optimized_u16()?;
+        let _crc = r.u32()?;
+        mods.push(SaveMod {
+            name,
+            version: semver::Version::new(major as u64, minor as u64, patch as u64),
+        });
+    }
+
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn reads_mods_from_sample_save() {
+        let mods = read_save_mods(&fixture("sample-save.zip")).unwrap();
+        let mods: Vec<(&str, String)> = mods
+            .iter()
+            .map(|m| (m.name.as_str(), m.version.to_string()))
+            .collect();
+        assert_eq!(
+            mods,
+            vec![
+                ("base", "1.1.110".to_string()),
+                ("flib", "0.12.9".to_string()),
+                ("even-distribution", "1.0.10".to_string()),
+                ("long-version", "300.0.1".to_string()),
+            ]
+        );
+    }
+
+    /// A header in the layout [`parse_header`] reads, field by field.
+    fn header(version: [u16; 4], mods: &[(&str, [u16; 3])]) -> Vec<u8> {
+        fn string(out: &mut Vec<u8>, s: &str) {
+            out.push(s.len() as u8);
+            out.extend_from_slice(s.as_bytes());
+        }
+        fn optimized_u16(out: &mut Vec<u8>, v: u16) {
+            if v < 0xFF {
+                out.push(v as u8);
+            } else {
+                out.push(0xFF);
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        let mut out = Vec::new();
+        for part in version {
+            out.extend_from_slice(&part.to_le_bytes());
+        }
+        out.push(0); // branch
+        string(&mut out, ""); // campaign
+        string(&mut out, "level"); // level name
+        string(&mut out, "base"); // base mod
+        out.extend_from_slice(&[0, 0, 0]); // difficulty, finished, player won
+        string(&mut out, ""); // next level
+        out.extend_from_slice(&[0, 0, 0, 0]); // can continue ... debug options
+        for part in &version[..3] {
+            optimized_u16(&mut out, *part); // loaded from
+        }
+        out.extend_from_slice(&version[3].to_le_bytes());
+        out.push(0); // allowed commands
+        out.push(mods.len() as u8);
+        for (name, version) in mods {
+            string(&mut out, name);
+            for part in version {
+                optimized_u16(&mut out, *part);
+            }
+            out.extend_from_slice(&0u32.to_le_bytes()); // CRC
+        }
+        out
+    }
+
+    fn names_and_versions(mods: &[SaveMod]) -> Vec<(String, String)> {
+        mods.iter()
+            .map(|m| (m.name.clone(), m.version.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_1_1_header() {
+        let data = header(
+            [1, 1, 110, 0],
+            &[("base", [1, 1, 110]), ("flib", [0, 12, 9])],
+        );
+        let mods = parse_header(&data).unwrap();
+        assert_eq!(
+            names_and_versions(&mods),
+            vec![
+                ("base".to_string(), "1.1.110".to_string()),
+                ("flib".to_string(), "0.12.9".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_2_0_header() {
+        let data = header(
+            [2, 0, 28, 0],
+            &[
+                ("base", [2, 0, 28]),
+                ("elevated-rails", [2, 0, 28]),
+                ("quality", [2, 0, 28]),
+                ("space-age", [2, 0, 28]),
+                ("long-version", [300, 0, 1]),
+            ],
+        );
+        let mods = parse_header(&data).unwrap();
+        assert_eq!(mods.len(), 5);
+        assert_eq!(mods[3].name, "space-age");
+        assert_eq!(mods[4].version, semver::Version::new(300, 0, 1));
+    }
+
+    #[test]
+    fn rejects_pre_0_17_header() {
+        let data = header([0, 16, 51, 0], &[("base", [0, 16, 51])]);
+        let err = parse_header(&data).unwrap_err().to_string();
+        assert!(err.contains("Factorio 0.16 are not supported"), "{}", err);
+    }
+
+    #[test]
+    fn reads_compressed_level_dat0() {
+        use std::io::Write;
+
+        let mut compressed =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        compressed
+            .write_all(&header([1, 1, 110, 0], &[("base", [1, 1, 110])]))
+            .unwrap();
+        let compressed = compressed.finish().unwrap();
+        assert!(is_zlib(&compressed));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("save.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.start_file("save/level.dat0", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&compressed).unwrap();
+        zip.finish().unwrap();
+
+        let mods = read_save_mods(&path).unwrap();
+        assert_eq!(mods[0].version, semver::Version::new(1, 1, 110));
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let data = [1u8, 0, 1, 0];
+        let err = parse_header(&data).unwrap_err().to_string();
+        assert!(err.contains("Unexpected end of header"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_garbage_mod_count() {
+        let mut data = header([1, 1, 110, 0], &[]);
+        // 0xFF and a full u32: about four billion mods that are not there
+        data.pop();
+        data.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let err = parse_header(&data).unwrap_err().to_string();
+        assert!(err.contains("Unexpected end of header"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_non_saves() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::factorio::test_support::write_mod_zip(dir.path(), "mod-a", "1.0.0", &[]);
+        assert!(read_save_mods(&path).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::installed;
+
+/// How zips get from the store into a mods directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Hard-link, falling back to a copy across filesystems.
+    #[default]
+    Hardlink,
+    Copy,
+}
+
+/// Mod zips shared by every instance, stored as `<sha1>.zip` so identical
+/// releases are only downloaded once.
+#[derive(Debug, Clone)]
+pub struct ZipStore {
+    dir: PathBuf,
+    link: LinkMode,
+}
+
+impl ZipStore {
+    pub fn new(dir: PathBuf, link: LinkMode) -> Self {
+        ZipStore { dir, link }
+    }
+
+    fn path(&self, sha1: &str) -> PathBuf {
+        self.dir.join(format!("{}.zip", sha1))
+    }
+
+    /// Put the zip with `sha1` at `dest` if the store has an intact copy.
+    /// A stored zip that no longer matches its hash is dropped.
+    pub fn place(&self, sha1: &str, dest: &Path) -> Result<bool> {
+        let stored = self.path(sha1);
+        if !stored.is_file() {
+            return Ok(false);
+        }
+        if installed::file_sha1(&stored)? != sha1 {
+            let _ = std::fs::remove_file(&stored);
+            return Ok(false);
+        }
+        link_or_copy(&stored, dest, self.link)?;
+        Ok(true)
+    }
+
+    /// Add `file`, already verified to hash to `sha1`.
+    pub fn insert(&self, sha1: &str, file: &Path) -> Result<()> {
+        let stored = self.path(sha1);
+        if stored.is_file() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        link_or_copy(file, &stored, self.link)
+    }
+
+    /// Delete every stored zip whose hash is not in `keep`. Returns the
+    /// deleted files with their sizes.
+    pub fn prune(&self, keep: &HashSet<String>) -> Result<Vec<(PathBuf, u64)>> {
+        let mut removed = Vec::new();
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(removed),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.dir.display()))
+            }
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            let Some(sha1) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".zip"))
+            else {
+                continue;
+            };
+            if keep.contains(sha1) {
+                continue;
+            }
+            let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete {}", path.display()))?;
+            removed.push((path, size));
+        }
+        removed.sort();
+        Ok(removed)
+    }
+}
+
+/// SHA-1 of every zip in `mods_dir`; a missing directory has none.
+pub fn referenced(mods_dir: &Path) -> Result<HashSet<String>> {
+    let mut hashes = HashSet::new();
+    let Ok(entries) = std::fs::read_dir(mods_dir) else {
+        return Ok(hashes);
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "zip") && path.is_file() {
+            hashes.insert(installed::file_sha1(&path)?);
+        }
+    }
+    Ok(hashes)
+}
+
+/// Copy through a temporary file so `dest` never holds a partial zip.
+fn link_or_copy(src: &Path, dest: &Path, mode: LinkMode) -> Result<()> {
+    if dest.exists() {
+        std::fs::remove_file(dest)
+            .with_context(|| format!("Failed to replace {}", dest.display()))?;
+    }
+    if mode == LinkMode::Hardlink && std::fs::hard_link(src, dest).is_ok() {
+        return Ok(());
+    }
+
+    let tmp = dest.with_extension("zip.tmp");
+    std::fs::copy(src, &tmp)
+        .with_context(|| format!("Failed to copy {} to {}", src.display(), tmp.display()))?;
+    std::fs::rename(&tmp, dest).with_context(|| format!("Failed to write {}", dest.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::test_support;
+
+    #[test]
+    fn places_stored_zips() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip = test_support::write_mod_zip(dir.path(), "mod-a", "1.0.0", &[]);
+        let sha1 = installed::file_sha1(&zip).unwrap();
+
+        for mode in [LinkMode::Hardlink, LinkMode::Copy] {
+            let store = ZipStore::new(dir.path().join("store"), mode);
+            let dest = dir.path().join("placed.zip");
+            assert!(!store.place("0000", &dest).unwrap());
+
+            store.insert(&sha1, &zip).unwrap();
+            assert!(store.place(&sha1, &dest).unwrap());
+            assert_eq!(installed::file_sha1(&dest).unwrap(), sha1);
+            std::fs::remove_dir_all(dir.path().join("store")).unwrap();
+        }
+    }
+
+    #[test]
+    fn drops_corrupt_zips() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ZipStore::new(dir.path().join("store"), LinkMode::Copy);
+        std::fs::create_dir_all(dir.path().join("store")).unwrap();
+        std::fs::write(dir.path().join("store/abc.zip"), b"not it").unwrap();
+
+        assert!(!store.place("abc", &dir.path().join("out.zip")).unwrap());
+        assert!(!dir.path().join("store/abc.zip").exists());
+    }
+
+    #[test]
+    fn prunes_unreferenced_zips() {
+        let dir = tempfile::tempdir().unwrap();
+        let mods = dir.path().join("mods");
+        std::fs::create_dir_all(&mods).unwrap();
+        let kept = test_support::write_mod_zip(&mods, "mod-a", "1.0.0", &[]);
+        let dropped = test_support::write_mod_zip(dir.path(), "mod-b", "1.0.0", &[]);
+
+        let store = ZipStore::new(dir.path().join("store"), LinkMode::Hardlink);
+        let kept_sha = installed::file_sha1(&kept).unwrap();
+        let dropped_sha = installed::file_sha1(&dropped).unwrap();
+        store.insert(&kept_sha, &kept).unwrap();
+        store.insert(&dropped_sha, &dropped).unwrap();
+
+        let keep = referenced(&mods).unwrap();
+        let removed = store.prune(&keep).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0, dir.path().join(format!("store/{}.zip", dropped_sha)));
+        assert!(dir.path().join(format!("store/{}.zip", kept_sha)).is_file());
+        assert!(referenced(&dir.path().join("nope")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+//! A tiny stand-in for the mod portal, serving files from a fixture
+//! directory over plain HTTP so install flows can be tested offline. Tests
+//! start it on a free port; `fml stub-portal` serves a directory of your own.
+//!
+//! A request for `/api/mods/flib/full` is answered with the first of
+//! `<root>/api/mods/flib/full`, `full.json` and `full.zip` that exists; the
+//! query string is ignored. Anything else is a 404. Every file is served
+//! with an ETag, and `If-None-Match` and `Range: bytes=N-` are honoured.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub struct StubPortal {
+    pub url: String,
+    requests: Arc<AtomicUsize>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl StubPortal {
+    /// Number of requests answered so far.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for StubPortal {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The fixture portal shipped in `tests/fixtures/portal`.
+#[cfg(test)]
+pub fn fixture_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("portal")
+}
+
+/// Serve `root` on a free local port until the returned handle is dropped.
+#[cfg(test)]
+pub async fn serve(root: PathBuf) -> StubPortal {
+    serve_with(root, None, 0).await
+}
+
+/// Serve `root` on `addr` until the returned handle is dropped.
+pub async fn serve_at(root: PathBuf, addr: std::net::SocketAddr) -> std::io::Result<StubPortal> {
+    let listener = TcpListener::bind(addr).await?;
+    Ok(serve_on(listener, root, None, 0))
+}
+
+/// Like [`serve`], but the first download longer than `cut_after` bytes is
+/// cut off there, as on a flaky connection.
+#[cfg(test)]
+pub async fn serve_flaky(root: PathBuf, cut_after: usize) -> StubPortal {
+    serve_with(root, Some(cut_after), 0).await
+}
+
+/// Like [`serve`], but the first `times` requests are turned away with
+/// HTTP 429 and `Retry-After: 0`.
+#[cfg(test)]
+pub async fn serve_rate_limited(root: PathBuf, times: usize) -> StubPortal {
+    serve_with(root, None, times).await
+}
+
+#[cfg(test)]
+async fn serve_with(root: PathBuf, cut_after: Option<usize>, rate_limited: usize) -> StubPortal {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    serve_on(listener, root, cut_after, rate_limited)
+}
+
+fn serve_on(
+    listener: TcpListener,
+    root: PathBuf,
+    cut_after: Option<usize>,
+    rate_limited: usize,
+) -> StubPortal {
+    let cut = Arc::new(AtomicBool::new(cut_after.is_some()));
+    let refusals = Arc::new(AtomicUsize::new(rate_limited));
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let task = tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let root = root.clone();
+            let counter = counter.clone();
+            let cut = cut.clone();
+            let refusals = refusals.clone();
+            tokio::spawn(async move {
+                let cut_after = cut_after.filter(|_| cut.load(Ordering::SeqCst));
+                let refuse = refusals
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok();
+                let _ = respond(stream, &root, &counter, cut_after, &cut, refuse).await;
+            });
+        }
+    });
+    StubPortal {
+        url,
+        requests,
+        task,
+    }
+}
+
+/// An address nothing listens on, for testing mirror fallback.
+#[cfg(test)]
+pub async fn dead_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    root: &Path,
+    counter: &AtomicUsize,
+    cut_after: Option<usize>,
+    cut: &AtomicBool,
+    refuse: bool,
+) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+
+    counter.fetch_add(1, Ordering::SeqCst);
+
+    let head = String::from_utf8_lossy(&head);
+    let header = |wanted: &str| {
+        head.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case(wanted)
+                .then(|| value.trim().to_string())
+        })
+    };
+    let if_none_match = header("if-none-match");
+    let range_from: Option<usize> = header("range")
+        .and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok());
+    let target = head.split_whitespace().nth(1).unwrap_or("/");
+    let path = target.split('?').next().unwrap_or("/");
+
+    let body = if path.split('/').any(|part| part == "..") {
+        None
+    } else {
+        let file = root.join(path.trim_start_matches('/'));
+        ["", ".json", ".zip"].iter().find_map(|ext| {
+            let candidate = PathBuf::from(format!("{}{}", file.display(), ext));
+            candidate
+                .is_file()
+                .then(|| std::fs::read(candidate).ok())
+                .flatten()
+        })
+    };
+
+    let mut extra = String::new();
+    let (status, body) = match body {
+        _ if refuse => {
+            extra.push_str("Retry-After: 0\r\n");
+            ("429 Too Many Requests", Vec::new())
+        }
+        Some(body) => {
+            let etag = format!("\"{}\"", sha1_smol::Sha1::from(&body).digest());
+            extra.push_str(&format!("ETag: {}\r\n", etag));
+            match range_from {
+                _ if if_none_match.as_deref() == Some(etag.as_str()) => {
+                    ("304 Not Modified", Vec::new())
+                }
+                Some(from) if from >= body.len() => ("416 Range Not Satisfiable", Vec::new()),
+                Some(from) => {
+                    extra.push_str(&format!(
+                        "Content-Range: bytes {}-{}/{}\r\n",
+                        from,
+                        body.len() - 1,
+                        body.len()
+                    ));
+                    ("206 Partial Content", body[from..].to_vec())
+                }
+                None => ("200 OK", body),
+            }
+        }
+        None => ("404 Not Found", b"{\"message\": \"Not found\"}".to_vec()),
+    };
+    let response_head = format!(
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        extra,
+        body.len()
+    );
+    let body = match cut_after {
+        Some(len) if path.starts_with("/download/") && body.len() > len => {
+            cut.store(false, Ordering::SeqCst);
+            &body[..len]
+        }
+        _ => &body[..],
+    };
+    stream.write_all(response_head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
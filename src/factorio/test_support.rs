@@ -0,0 +1,65 @@
+//! Factories for the mods, releases and zips that tests build by hand.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::installed::InstalledMod;
+use super::types::{Mod, Release};
+
+/// An installed mod for Factorio 1.1 with the given dependency strings.
+pub fn make_installed(name: &str, version: &str, deps: &[&str]) -> InstalledMod {
+    InstalledMod {
+        name: name.to_string(),
+        version: semver::Version::parse(version).unwrap(),
+        title: name.to_string(),
+        factorio_version: "1.1".to_string(),
+        dependencies: deps.iter().map(|d| d.parse().unwrap()).collect(),
+        file_name: format!("{}_{}.zip", name, version),
+    }
+}
+
+/// A portal mod with the given releases.
+pub fn make_mod(name: &str, releases: Vec<Release>) -> Mod {
+    Mod {
+        name: name.to_string(),
+        releases,
+    }
+}
+
+/// A portal release of `name` with the given dependency strings.
+pub fn make_release(name: &str, version: &str, factorio_version: &str, deps: &[&str]) -> Release {
+    Release {
+        download_url: format!("/download/{}/{}", name, version),
+        file_name: format!("{}_{}.zip", name, version),
+        version: semver::Version::parse(version).unwrap(),
+        factorio_version: factorio_version.to_string(),
+        sha1: String::new(),
+        dependencies: deps.iter().map(|d| d.parse().unwrap()).collect(),
+    }
+}
+
+/// Write a minimal mod zip (`name_version/info.json`) into `dir`.
+pub fn write_mod_zip(dir: &Path, name: &str, version: &str, dependencies: &[&str]) -> PathBuf {
+    let path = dir.join(format!("{}_{}.zip", name, version));
+    std::fs::write(&path, mod_zip_bytes(name, version, dependencies)).unwrap();
+    path
+}
+
+/// The zip [`write_mod_zip`] writes, in memory.
+pub fn mod_zip_bytes(name: &str, version: &str, dependencies: &[&str]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let info = serde_json::json!({
+        "name": name,
+        "version": version,
+        "title": name,
+        "factorio_version": "1.1",
+        "dependencies": dependencies,
+    });
+    zip.start_file(
+        format!("{}_{}/info.json", name, version),
+        zip::write::SimpleFileOptions::default(),
+    )
+    .unwrap();
+    zip.write_all(info.to_string().as_bytes()).unwrap();
+    zip.finish().unwrap().into_inner()
+}
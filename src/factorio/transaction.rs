@@ -0,0 +1,315 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use super::credentials::Credentials;
+use super::installed::InstalledMod;
+use super::installer;
+use super::mod_list::ModList;
+use super::portal::SharedPortal;
+use super::types::Release;
+
+/// Changes to a mods directory that are applied completely or not at all.
+///
+/// Downloads go to a staging directory and are only moved into the mods
+/// directory once every one of them has been verified. Zips that get
+/// replaced or deleted are moved aside rather than removed, and mod-list.json
+/// is kept as it was before its first write, so a failure at any later step
+/// can put everything back. Dropping an unfinished transaction rolls it back.
+#[derive(Debug)]
+pub struct Transaction {
+    mods_dir: String,
+    staging: PathBuf,
+    backup: PathBuf,
+    journal: Vec<Step>,
+    /// mod-list.json before the first write; `Some(None)` if there was none.
+    mod_list: Option<Option<Vec<u8>>>,
+    done: bool,
+}
+
+#[derive(Debug)]
+enum Step {
+    /// A zip moved into the mods directory.
+    Placed(PathBuf),
+    /// A zip moved out of the mods directory, and where it went.
+    MovedAside { from: PathBuf, to: PathBuf },
+}
+
+/// Where downloads for `mods_dir` are staged: a hidden directory beside it,
+/// so Factorio never sees a half-finished set and moving a zip in is a
+/// rename on the same filesystem.
+pub fn staging_dir(mods_dir: &Path) -> PathBuf {
+    let name = mods_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "mods".to_string());
+    mods_dir
+        .parent()
+        .unwrap_or(mods_dir)
+        .join(format!(".fml-staging-{}", name))
+}
+
+impl Transaction {
+    /// Start a transaction on `mods_dir`. If the directory beside it is not
+    /// writable, downloads are staged in the system temp directory instead.
+    pub fn begin(mods_dir: &str) -> Result<Self> {
+        let mut staging = staging_dir(Path::new(mods_dir));
+        if std::fs::create_dir_all(&staging).is_err() {
+            let key = sha1_smol::Sha1::from(mods_dir).digest().to_string();
+            staging = std::env::temp_dir().join(format!("fml-staging-{}", &key[..12]));
+            std::fs::create_dir_all(&staging).with_context(|| {
+                format!("Failed to create staging directory {}", staging.display())
+            })?;
+        }
+        let backup = staging.join(format!("backup-{}", std::process::id()));
+
+        Ok(Transaction {
+            mods_dir: mods_dir.to_string(),
+            staging,
+            backup,
+            journal: Vec::new(),
+            mod_list: None,
+            done: false,
+        })
+    }
+
+    pub fn mods_dir(&self) -> &str {
+        &self.mods_dir
+    }
+
+    /// Download `releases` into the staging directory and, once all of them
+    /// have verified, move them into the mods directory. A zip of the same
+    /// name that is already there is moved aside.
+    pub async fn download(
+        &mut self,
+        portal: &SharedPortal,
+        releases: &[(String, Release)],
+        credentials: &Credentials,
+    ) -> Result<()> {
+        if releases.is_empty() {
+            return Ok(());
+        }
+        let staging = self.staging.to_string_lossy().into_owned();
+        installer::download_all(portal, releases, credentials, &staging).await?;
+
+        for (_, release) in releases {
+            let dest = Path::new(&self.mods_dir).join(&release.file_name);
+            if dest.exists() {
+                self.move_aside(&dest)?;
+            }
+            move_file(&self.staging.join(&release.file_name), &dest)?;
+            self.journal.push(Step::Placed(dest));
+        }
+        Ok(())
+    }
+
+    /// Delete the zip of an installed mod, keeping it until the transaction
+    /// is committed.
+    pub fn delete_mod(&mut self, m: &InstalledMod) -> Result<()> {
+        let path = Path::new(&self.mods_dir).join(&m.file_name);
+        if !path.is_file() {
+            bail!("Mod file not found: {} in {}", m.file_name, self.mods_dir);
+        }
+        self.move_aside(&path)
+    }
+
+    /// Write mod-list.json, remembering the previous contents the first time.
+    pub fn save_mod_list(&mut self, mod_list: &ModList) -> Result<()> {
+        if self.mod_list.is_none() {
+            let path = Path::new(&self.mods_dir).join("mod-list.json");
+            self.mod_list = Some(match std::fs::read(&path) {
+                Ok(contents) => Some(contents),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", path.display()))
+                }
+            });
+        }
+        mod_list.save(&self.mods_dir)
+    }
+
+    /// Commit on `Ok`, roll back on `Err`, and pass `result` through.
+    pub fn finish<T>(mut self, result: Result<T>) -> Result<T> {
+        match result {
+            Ok(value) => {
+                self.commit();
+                Ok(value)
+            }
+            Err(e) => match self.rollback() {
+                Ok(()) => Err(e),
+                Err(undo) => Err(anyhow!("{} (rolling back also failed: {})", e, undo)),
+            },
+        }
+    }
+
+    fn move_aside(&mut self, path: &Path) -> Result<()> {
+        std::fs::create_dir_all(&self.backup)
+            .with_context(|| format!("Failed to create {}", self.backup.display()))?;
+        let to = self
+            .backup
+            .join(format!("{}-{}", self.journal.len(), file_name(path)));
+        move_file(path, &to)?;
+        self.journal.push(Step::MovedAside {
+            from: path.to_path_buf(),
+            to,
+        });
+        Ok(())
+    }
+
+    fn commit(&mut self) {
+        self.done = true;
+        self.journal.clear();
+        self.clean_up();
+    }
+
+    /// Undo every step in reverse order. Keeps going past failures so as
+    /// much as possible is restored, and reports the first one.
+    fn rollback(&mut self) -> Result<()> {
+        self.done = true;
+        let mut first_error: Option<anyhow::Error> = None;
+        let mut record = |result: Result<()>| {
+            if let Err(e) = result {
+                first_error.get_or_insert(e);
+            }
+        };
+
+        while let Some(step) = self.journal.pop() {
+            record(match step {
+                Step::Placed(path) => match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)
+                        .with_context(|| format!("Failed to delete {}", path.display())),
+                    _ => Ok(()),
+                },
+                Step::MovedAside { from, to } => move_file(&to, &from),
+            });
+        }
+
+        let path = Path::new(&self.mods_dir).join("mod-list.json");
+        record(match self.mod_list.take() {
+            Some(Some(contents)) => std::fs::write(&path, contents)
+                .with_context(|| format!("Failed to restore {}", path.display())),
+            Some(None) => match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("Failed to delete {}", path.display()))
+                }
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        });
+
+        match first_error {
+            Some(e) => Err(e),
+            None => {
+                self.clean_up();
+                Ok(())
+            }
+        }
+    }
+
+    /// Drop the backups, and the staging directory unless it still holds
+    /// partial downloads to resume.
+    fn clean_up(&self) {
+        let _ = std::fs::remove_dir_all(&self.backup);
+        let _ = std::fs::remove_dir(&self.staging);
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.rollback();
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Rename `from` to `to`, copying through a temporary file when they are on
+/// different filesystems.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let tmp = to.with_extension("zip.tmp");
+    std::fs::copy(from, &tmp)
+        .with_context(|| format!("Failed to copy {} to {}", from.display(), tmp.display()))?;
+    std::fs::rename(&tmp, to).with_context(|| format!("Failed to write {}", to.display()))?;
+    std::fs::remove_file(from).with_context(|| format!("Failed to delete {}", from.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factorio::fake_portal::FixturePortal;
+    use crate::factorio::installed;
+    use crate::factorio::stub_portal;
+    use crate::factorio::test_support;
+    use std::sync::Arc;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            username: "user".to_string(),
+            token: "token".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn rolls_back_placed_and_deleted_zips() {
+        let portal: SharedPortal = Arc::new(FixturePortal::new(stub_portal::fixture_root()));
+        let dir = tempfile::tempdir().unwrap();
+        let mods = dir.path().join("mods");
+        std::fs::create_dir_all(&mods).unwrap();
+        let mods_dir = mods.to_str().unwrap();
+        // Zips keep whatever name they were installed under
+        let old = mods.join("old.zip");
+        std::fs::rename(test_support::write_mod_zip(&mods, "old", "1.0.0", &[]), &old).unwrap();
+        let installed_old = installed::read_installed_mods(mods_dir).unwrap().remove(0);
+        std::fs::write(mods.join("mod-list.json"), "before").unwrap();
+
+        let lib = portal.fetch_mod_details("lib").await.unwrap().releases[0].clone();
+        let mut tx = Transaction::begin(mods_dir).unwrap();
+        let result: Result<()> = async {
+            tx.download(&portal, &[("lib".to_string(), lib)], &credentials())
+                .await?;
+            assert!(mods.join("lib_1.0.0.zip").is_file());
+            tx.delete_mod(&installed_old)?;
+            assert!(!old.exists());
+            tx.save_mod_list(&ModList::new())?;
+            bail!("later step failed")
+        }
+        .await;
+
+        let err = tx.finish(result).unwrap_err().to_string();
+        assert_eq!(err, "later step failed");
+        assert!(!mods.join("lib_1.0.0.zip").exists());
+        assert!(old.is_file());
+        assert_eq!(std::fs::read_to_string(mods.join("mod-list.json")).unwrap(), "before");
+        assert!(!staging_dir(&mods).exists());
+    }
+
+    #[tokio::test]
+    async fn commits_and_stages_outside_the_mods_dir() {
+        let portal: SharedPortal = Arc::new(FixturePortal::new(stub_portal::fixture_root()));
+        let dir = tempfile::tempdir().unwrap();
+        let mods = dir.path().join("mods");
+        std::fs::create_dir_all(&mods).unwrap();
+        let replaced = test_support::write_mod_zip(&mods, "lib", "1.0.0", &[]);
+
+        let lib = portal.fetch_mod_details("lib").await.unwrap().releases[0].clone();
+        let sha1 = lib.sha1.clone();
+        let mut tx = Transaction::begin(mods.to_str().unwrap()).unwrap();
+        assert!(staging_dir(&mods).is_dir());
+        let result = tx
+            .download(&portal, &[("lib".to_string(), lib)], &credentials())
+            .await;
+        tx.finish(result).unwrap();
+
+        assert_eq!(installed::file_sha1(&replaced).unwrap(), sha1);
+        let entries: Vec<_> = std::fs::read_dir(&mods).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(!staging_dir(&mods).exists());
+    }
+}
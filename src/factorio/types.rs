@@ -10,9 +10,6 @@ use std::sync::LazyLock;
 #[derive(Debug, Clone)]
 pub struct Mod {
     pub name: String,
-    pub title: String,
-    pub summary: String,
-    pub downloads_count: u64,
     pub releases: Vec<Release>,
 }
 
@@ -31,6 +28,8 @@ pub struct Dependency {
     pub name: String,
     pub version_req: semver::VersionReq,
     pub dep_type: DependencyType,
+    /// False for `~` dependencies, which are required but do not change load order.
+    pub affects_load_order: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -84,45 +83,62 @@ impl FromStr for Dependency {
             name,
             version_req,
             dep_type,
+            affects_load_order: prefix != Some("~"),
         })
     }
 }
 
-// --- Mod list entry (from GET /api/mods) ---
+/// Mods that ship with the game and cannot be downloaded from the portal.
+const BUILTIN_MODS: &[&str] = &["base", "space-age", "quality", "elevated-rails"];
+
+pub fn is_builtin_mod(name: &str) -> bool {
+    BUILTIN_MODS.contains(&name)
+}
+
+// --- Mod spec (command line `name` or `name@version`) ---
 
 #[derive(Debug, Clone)]
-pub struct ModListEntry {
+pub struct ModSpec {
     pub name: String,
-    pub title: String,
-    pub downloads_count: u64,
-    pub summary: String,
+    pub version_req: semver::VersionReq,
 }
 
-// --- Server settings ---
+impl FromStr for ModSpec {
+    type Err = anyhow::Error;
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct ServerSettings {
-    #[serde(default)]
-    pub username: String,
-    #[serde(default)]
-    pub token: String,
-}
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, version) = match s.split_once('@') {
+            Some((name, version)) => (name.trim(), Some(version.trim())),
+            None => (s.trim(), None),
+        };
 
-pub fn read_server_settings(path: &str) -> Result<ServerSettings> {
-    let contents = std::fs::read_to_string(path)
-        .with_context(|| format!("Failed to read server settings from: {}", path))?;
-    let settings: ServerSettings = serde_json::from_str(&contents)
-        .with_context(|| format!("Failed to parse server settings: {}", path))?;
+        if name.is_empty() {
+            bail!("Invalid mod spec '{}': missing mod name", s);
+        }
 
-    if settings.username.is_empty() || settings.token.is_empty() {
-        bail!(
-            "Valid Factorio credentials (username and token) are required in {}. \
-             Both 'username' and 'token' fields must be non-empty.",
-            path
-        );
+        let version_req = match version {
+            Some(v) => {
+                let version = semver::Version::parse(v)
+                    .with_context(|| format!("Invalid version '{}' in mod spec '{}'", v, s))?;
+                semver::VersionReq::parse(&format!("={}", version))?
+            }
+            None => semver::VersionReq::STAR,
+        };
+
+        Ok(ModSpec {
+            name: name.to_string(),
+            version_req,
+        })
     }
+}
+
+// --- Mod list entry (from GET /api/mods) ---
 
-    Ok(settings)
+#[derive(Debug, Clone)]
+pub struct ModListEntry {
+    pub name: String,
+    pub title: String,
+    pub downloads_count: u64,
 }
 
 // --- Factorio version detection ---
@@ -173,6 +189,7 @@ mod tests {
         assert_eq!(dep.name, "base");
         assert_eq!(dep.dep_type, DependencyType::Required);
         assert_eq!(dep.version_req, semver::VersionReq::STAR);
+        assert!(dep.affects_load_order);
     }
 
     #[test]
@@ -210,6 +227,7 @@ mod tests {
         let dep: Dependency = "~ some-mod >= 1.0.0".parse().unwrap();
         assert_eq!(dep.name, "some-mod");
         assert_eq!(dep.dep_type, DependencyType::Required);
+        assert!(!dep.affects_load_order);
         assert!(dep.version_req.matches(&semver::Version::new(1, 0, 0)));
         assert!(dep.version_req.matches(&semver::Version::new(2, 0, 0)));
         assert!(!dep.version_req.matches(&semver::Version::new(0, 9, 0)));
@@ -238,4 +256,25 @@ mod tests {
         assert_eq!(dep.name, "Krastorio 2");
         assert_eq!(dep.dep_type, DependencyType::Optional);
     }
+
+    #[test]
+    fn parse_mod_spec_name_only() {
+        let spec: ModSpec = "flib".parse().unwrap();
+        assert_eq!(spec.name, "flib");
+        assert_eq!(spec.version_req, semver::VersionReq::STAR);
+    }
+
+    #[test]
+    fn parse_mod_spec_with_version() {
+        let spec: ModSpec = "flib@0.15.0".parse().unwrap();
+        assert_eq!(spec.name, "flib");
+        assert!(spec.version_req.matches(&semver::Version::new(0, 15, 0)));
+        assert!(!spec.version_req.matches(&semver::Version::new(0, 15, 1)));
+    }
+
+    #[test]
+    fn parse_mod_spec_invalid() {
+        assert!("@1.0.0".parse::<ModSpec>().is_err());
+        assert!("flib@latest".parse::<ModSpec>().is_err());
+    }
 }
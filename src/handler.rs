@@ -1,10 +1,19 @@
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 
 use crate::app::{ActiveBlock, App, ManageMod, Tab};
-use crate::event::{AppEvent, InstallResult};
-use crate::factorio::{api, installed, mod_list::ModList, resolver};
+use crate::event::{AppEvent, InstallResult, SyncResult, UpdateResult};
+use crate::factorio::credentials::Credentials;
+use crate::factorio::installed::{self, InstalledMod};
+use crate::factorio::installer::{self, RemoveMode};
+use crate::factorio::lockfile;
+use crate::factorio::mod_list::ModList;
+use crate::factorio::mod_settings::{SettingKind, SettingValue};
+use crate::factorio::portal::SharedPortal;
+use crate::factorio::transaction::Transaction;
 
 pub fn handle_event(event: AppEvent, app: &mut App, tx: mpsc::UnboundedSender<AppEvent>) {
     match event {
@@ -12,51 +21,51 @@ pub fn handle_event(event: AppEvent, app: &mut App, tx: mpsc::UnboundedSender<Ap
         AppEvent::Tick => app.clear_expired_status(),
         AppEvent::ModListLoaded(result) => match result {
             Ok(mods) => {
+                // A refresh after the cached list keeps the selection
                 app.install_mods = mods;
                 app.loading = false;
                 if app.install_mods.is_empty() {
                     app.set_status("No mods found for this Factorio version".to_string());
-                } else {
+                    app.install_selected = None;
+                } else if app
+                    .install_selected
+                    .is_none_or(|sel| sel >= app.filtered_install_mods().len())
+                {
                     app.install_selected = Some(0);
                 }
             }
+            Err(e) if !app.install_mods.is_empty() => {
+                app.set_status(format!("Failed to refresh mod list, showing cached: {}", e));
+            }
             Err(e) => {
                 app.loading = false;
                 app.set_status(format!("Failed to load mod list: {}", e));
             }
         },
+        AppEvent::DownloadProgress(progress) => {
+            if app.installing {
+                let done = progress.total == Some(progress.downloaded);
+                let pos = app
+                    .downloads
+                    .iter()
+                    .position(|d| d.file_name == progress.file_name);
+                match pos {
+                    Some(pos) if done => {
+                        app.downloads.remove(pos);
+                    }
+                    Some(pos) => app.downloads[pos] = progress,
+                    None if done => {}
+                    None => app.downloads.push(progress),
+                }
+            }
+        }
         AppEvent::ModInstalled(result) => {
             app.installing = false;
+            app.downloads.clear();
             match result {
                 Ok(install_result) => {
-                    // Build set of previously known mods with their enabled/pending state
-                    let prev_state: HashMap<String, (bool, bool)> = app
-                        .manage_mods
-                        .iter()
-                        .map(|m| (m.installed_mod.name.clone(), (m.enabled, m.pending)))
-                        .collect();
-
-                    // Refresh manage mods from disk
-                    app.manage_mods = install_result
-                        .installed_mods
-                        .into_iter()
-                        .map(|m| {
-                            let (enabled, pending) = prev_state
-                                .get(&m.name)
-                                .copied()
-                                .unwrap_or((true, true)); // new mods default to enabled + pending
-                            ManageMod {
-                                installed_mod: m,
-                                enabled,
-                                pending,
-                            }
-                        })
-                        .collect();
-
-                    // Ensure selection is valid
-                    if !app.manage_mods.is_empty() && app.manage_selected.is_none() {
-                        app.manage_selected = Some(0);
-                    }
+                    // New mods default to enabled + pending
+                    app.replace_installed(install_result.installed_mods, |_| (true, true));
 
                     if install_result.dependency_count > 0 {
                         app.set_status(format!(
@@ -91,12 +100,75 @@ pub fn handle_event(event: AppEvent, app: &mut App, tx: mpsc::UnboundedSender<Ap
                 app.set_status(format!("Delete failed: {}", e));
             }
         },
+        AppEvent::ModsSynced(result) => {
+            app.installing = false;
+            app.downloads.clear();
+            match result {
+                Ok(sync) => {
+                    // Keep unsaved toggles; newly downloaded mods take their
+                    // state from mod-list.json, which sync already updated
+                    let mod_list = sync.mod_list;
+                    app.replace_installed(sync.installed_mods, |m| {
+                        (mod_list.loads(&m.name), false)
+                    });
+
+                    let report = sync.report;
+                    if report.failed.is_empty() {
+                        app.set_status(format!(
+                            "Synced mod-list.json: {} mod(s) downloaded",
+                            report.downloaded.len()
+                        ));
+                    } else {
+                        let failed: Vec<&str> =
+                            report.failed.iter().map(|(n, _)| n.as_str()).collect();
+                        app.set_status(format!(
+                            "Synced mod-list.json: {} mod(s) downloaded, could not install {}",
+                            report.downloaded.len(),
+                            failed.join(", ")
+                        ));
+                    }
+                }
+                Err(e) => {
+                    app.set_status(format!("Sync failed: {}", e));
+                }
+            }
+        }
+        AppEvent::ModsUpdated(result) => {
+            app.installing = false;
+            app.downloads.clear();
+            match result {
+                Ok(update) => {
+                    // Upgraded mods keep their state; new dependencies are pending
+                    app.replace_installed(update.installed_mods, |_| (true, true));
+
+                    let report = update.report;
+                    if let Some((name, reason)) = report.failed.first() {
+                        app.set_status(format!("Update of {} failed: {}", name, reason));
+                    } else if let Some(held) = report.held_back.first() {
+                        app.set_status(format!(
+                            "{} {} held back: required by {}",
+                            held.name, held.latest, held.required_by
+                        ));
+                    } else if let Some(upgrade) = report.upgraded.first() {
+                        app.set_status(format!(
+                            "Updated {} {} -> {}",
+                            upgrade.name, upgrade.from, upgrade.to.version
+                        ));
+                    } else {
+                        app.set_status("Already up to date".to_string());
+                    }
+                }
+                Err(e) => {
+                    app.set_status(format!("Update failed: {}", e));
+                }
+            }
+        }
         AppEvent::InstalledModsLoaded(result) => match result {
             Ok((mods, mod_list)) => {
                 app.manage_mods = mods
                     .into_iter()
                     .map(|m| {
-                        let enabled = mod_list.is_enabled(&m.name);
+                        let enabled = mod_list.loads(&m.name);
                         ManageMod {
                             enabled,
                             installed_mod: m,
@@ -112,7 +184,42 @@ pub fn handle_event(event: AppEvent, app: &mut App, tx: mpsc::UnboundedSender<Ap
                 app.set_status(format!("Failed to read installed mods: {}", e));
             }
         },
-        AppEvent::Error(msg) => {
+        AppEvent::CredentialsChecked(result) => {
+            let source = app
+                .credentials_source
+                .as_ref()
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            match result {
+                Ok(true) => {}
+                Ok(false) => {
+                    app.credentials = None;
+                    app.set_status(format!(
+                        "The mod portal rejected the credentials from {}; browsing read-only",
+                        source
+                    ));
+                }
+                Err(e) => {
+                    app.set_status(format!(
+                        "Could not validate the credentials from {}: {}",
+                        source, e
+                    ));
+                }
+            }
+        }
+        AppEvent::SettingsLoaded(result) => match result {
+            Ok((settings, mod_settings)) => {
+                app.settings = settings;
+                app.mod_settings = Some(mod_settings);
+                if !app.settings.is_empty() {
+                    app.settings_selected = Some(0);
+                }
+            }
+            Err(e) => {
+                app.set_status(format!("Failed to read mod settings: {}", e));
+            }
+        },
+        AppEvent::Warning(msg) => {
             app.set_status(msg);
         }
     }
@@ -142,6 +249,33 @@ fn save_mod_list(app: &mut App) {
     }
 }
 
+fn save_mod_settings(app: &mut App) {
+    let Some(mod_settings) = &app.mod_settings else {
+        return;
+    };
+    match mod_settings.save(&app.mods_dir) {
+        Ok(()) => {
+            app.settings_dirty = false;
+            app.set_status("Saved mod-settings.dat".to_string());
+        }
+        Err(e) => {
+            app.set_status(format!("Failed to save: {}", e));
+        }
+    }
+}
+
+/// Save mod-list.json and, if settings were edited, mod-settings.dat.
+fn save_all(app: &mut App) {
+    save_mod_list(app);
+    let failed = app
+        .status_message
+        .as_ref()
+        .is_some_and(|(msg, _)| msg.starts_with("Failed"));
+    if !failed && app.settings_dirty {
+        save_mod_settings(app);
+    }
+}
+
 fn handle_key(key: KeyEvent, app: &mut App, tx: mpsc::UnboundedSender<AppEvent>) {
     // Quit popup takes priority
     if app.show_quit_popup {
@@ -157,14 +291,15 @@ fn handle_key(key: KeyEvent, app: &mut App, tx: mpsc::UnboundedSender<AppEvent>)
     }
 
     if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('s') {
-        save_mod_list(app);
+        save_all(app);
         return;
     }
 
     if key.code == KeyCode::Tab {
         match app.tab {
             Tab::Manage => app.select_tab(Tab::Install),
-            Tab::Install => app.select_tab(Tab::Manage),
+            Tab::Install => app.select_tab(Tab::Settings),
+            Tab::Settings => app.select_tab(Tab::Manage),
         }
         return;
     }
@@ -173,6 +308,8 @@ fn handle_key(key: KeyEvent, app: &mut App, tx: mpsc::UnboundedSender<AppEvent>)
         ActiveBlock::ManageModList => handle_manage_keys(key, app, tx),
         ActiveBlock::InstallModList => handle_install_list_keys(key, app, tx),
         ActiveBlock::InstallSearch => handle_search_keys(key, app),
+        ActiveBlock::SettingsList => handle_settings_keys(key, app),
+        ActiveBlock::SettingsEdit => handle_settings_edit_keys(key, app),
         ActiveBlock::QuitPopup => handle_quit_popup(key, app),
     }
 }
@@ -190,19 +327,99 @@ fn handle_manage_keys(key: KeyEvent, app: &mut App, tx: mpsc::UnboundedSender<Ap
             }
         }
         KeyCode::Char('d') => {
-            // Delete mod
+            // Delete mod, unless an enabled mod still requires it
             if let Some(sel) = app.manage_selected {
                 if let Some(m) = app.manage_mods.get(sel) {
                     let name = m.installed_mod.name.clone();
-                    let version = m.installed_mod.version.clone();
+                    let mods: Vec<InstalledMod> = app
+                        .manage_mods
+                        .iter()
+                        .map(|m| m.installed_mod.clone())
+                        .collect();
+                    let mut enabled = ModList::new();
+                    for m in &app.manage_mods {
+                        enabled.set_enabled(&m.installed_mod.name, m.enabled);
+                    }
+
+                    let plan = match installer::plan_removal(
+                        &mods,
+                        &enabled,
+                        std::slice::from_ref(&name),
+                        RemoveMode::Refuse,
+                    ) {
+                        Ok(plan) => plan,
+                        Err(e) => {
+                            app.set_status(e.to_string());
+                            return;
+                        }
+                    };
+
                     let mods_dir = app.mods_dir.clone();
+                    let lock_path = app.lock_path.clone();
                     tokio::spawn(async move {
-                        let result = installed::delete_mod(&name, &version, &mods_dir);
+                        let result = installer::remove(&plan, &mods_dir, &lock_path);
                         let _ = tx.send(AppEvent::ModDeleted(result.map(|_| name)));
                     });
                 }
             }
         }
+        KeyCode::Char('u') => {
+            // Upgrade the selected mod to its newest compatible release
+            if app.installing {
+                app.set_status("Installation already in progress...".to_string());
+                return;
+            }
+            let Some(creds) = app.require_credentials() else {
+                return;
+            };
+            if let Some(sel) = app.manage_selected {
+                if let Some(m) = app.manage_mods.get(sel) {
+                    let name = m.installed_mod.name.clone();
+                    app.installing = true;
+                    app.set_status(format!("Updating {}...", name));
+
+                    let factorio_version = app.factorio_version.clone();
+                    let portal = reporting_portal(app, &tx);
+                    let mods_dir = app.mods_dir.clone();
+                    let lock_path = app.lock_path.clone();
+                    tokio::spawn(async move {
+                        let result = do_update(
+                            &name,
+                            &factorio_version,
+                            &portal,
+                            &creds,
+                            &mods_dir,
+                            &lock_path,
+                        )
+                        .await;
+                        let _ = tx.send(AppEvent::ModsUpdated(result));
+                    });
+                }
+            }
+        }
+        KeyCode::Char('s') => {
+            // Download everything listed in mod-list.json but not installed
+            if app.installing {
+                app.set_status("Installation already in progress...".to_string());
+                return;
+            }
+            let Some(creds) = app.require_credentials() else {
+                return;
+            };
+
+            app.installing = true;
+            app.set_status("Syncing mods from mod-list.json...".to_string());
+
+            let factorio_version = app.factorio_version.clone();
+            let portal = reporting_portal(app, &tx);
+            let mods_dir = app.mods_dir.clone();
+            let lock_path = app.lock_path.clone();
+            tokio::spawn(async move {
+                let result =
+                    do_sync(&factorio_version, &portal, &creds, &mods_dir, &lock_path).await;
+                let _ = tx.send(AppEvent::ModsSynced(result));
+            });
+        }
         _ => {}
     }
 }
@@ -219,6 +436,9 @@ fn handle_install_list_keys(key: KeyEvent, app: &mut App, tx: mpsc::UnboundedSen
                 app.set_status("Installation already in progress...".to_string());
                 return;
             }
+            let Some(creds) = app.require_credentials() else {
+                return;
+            };
 
             let filtered = app.filtered_install_mods();
             if let Some(sel) = app.install_selected {
@@ -234,9 +454,9 @@ fn handle_install_list_keys(key: KeyEvent, app: &mut App, tx: mpsc::UnboundedSen
                     app.set_status(format!("Installing {}...", mod_name));
 
                     let factorio_version = app.factorio_version.clone();
-                    let username = app.server_settings.username.clone();
-                    let token = app.server_settings.token.clone();
+                    let portal = reporting_portal(app, &tx);
                     let mods_dir = app.mods_dir.clone();
+                    let lock_path = app.lock_path.clone();
 
                     // Build installed mods map for the resolver
                     let installed_map: HashMap<String, semver::Version> = app
@@ -255,10 +475,11 @@ fn handle_install_list_keys(key: KeyEvent, app: &mut App, tx: mpsc::UnboundedSen
                         let result = do_install(
                             &mod_name,
                             &factorio_version,
-                            &username,
-                            &token,
+                            &portal,
+                            &creds,
                             &mods_dir,
                             &installed_map,
+                            &lock_path,
                         )
                         .await;
                         let _ = tx_clone.send(AppEvent::ModInstalled(result));
@@ -311,17 +532,80 @@ fn handle_search_keys(key: KeyEvent, app: &mut App) {
     }
 }
 
+fn handle_settings_keys(key: KeyEvent, app: &mut App) {
+    let selected = app.settings_selected.and_then(|sel| app.settings.get(sel)).cloned();
+
+    match key.code {
+        KeyCode::Up => app.move_up(),
+        KeyCode::Down => app.move_down(),
+        KeyCode::Enter => {
+            let Some(proto) = selected else {
+                return;
+            };
+            if proto.kind == SettingKind::Bool {
+                let current = matches!(app.setting_value(&proto), Some(SettingValue::Bool(true)));
+                if let Some(mod_settings) = &mut app.mod_settings {
+                    mod_settings.set_value(&proto, &SettingValue::Bool(!current));
+                    app.settings_dirty = true;
+                }
+            } else {
+                app.settings_input = app
+                    .setting_value(&proto)
+                    .map(|v| v.to_string())
+                    .unwrap_or_default();
+                app.active_block = ActiveBlock::SettingsEdit;
+            }
+        }
+        KeyCode::Char('r') => {
+            // Drop the stored value so the default from settings.lua applies
+            if let (Some(proto), Some(mod_settings)) = (selected, &mut app.mod_settings) {
+                mod_settings.reset(&proto);
+                app.settings_dirty = true;
+                app.set_status(format!("Reset {} to its default", proto.name));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn handle_settings_edit_keys(key: KeyEvent, app: &mut App) {
+    match key.code {
+        KeyCode::Esc => {
+            app.active_block = ActiveBlock::SettingsList;
+        }
+        KeyCode::Enter => {
+            let Some(proto) = app.settings_selected.and_then(|sel| app.settings.get(sel)) else {
+                app.active_block = ActiveBlock::SettingsList;
+                return;
+            };
+            match proto.parse_value(&app.settings_input) {
+                Ok(value) => {
+                    if let Some(mod_settings) = &mut app.mod_settings {
+                        mod_settings.set_value(proto, &value);
+                        app.settings_dirty = true;
+                    }
+                    app.active_block = ActiveBlock::SettingsList;
+                }
+                Err(e) => app.set_status(e.to_string()),
+            }
+        }
+        KeyCode::Char(c) => app.settings_input.push(c),
+        KeyCode::Backspace => {
+            app.settings_input.pop();
+        }
+        _ => {}
+    }
+}
+
 fn handle_quit_popup(key: KeyEvent, app: &mut App) {
     match key.code {
         KeyCode::Char('y') => {
-            save_mod_list(app);
+            save_all(app);
+            #[allow(clippy::unnecessary_map_or)]
             if app.status_message.as_ref().map_or(false, |(msg, _)| msg.starts_with("Failed")) {
                 // Save failed, cancel quit
                 app.show_quit_popup = false;
-                app.active_block = match app.tab {
-                    Tab::Manage => ActiveBlock::ManageModList,
-                    Tab::Install => ActiveBlock::InstallModList,
-                };
+                app.active_block = app.tab_block();
                 return;
             }
             app.should_quit = true;
@@ -331,44 +615,50 @@ fn handle_quit_popup(key: KeyEvent, app: &mut App) {
         }
         KeyCode::Esc => {
             app.show_quit_popup = false;
-            app.active_block = match app.tab {
-                Tab::Manage => ActiveBlock::ManageModList,
-                Tab::Install => ActiveBlock::InstallModList,
-            };
+            app.active_block = app.tab_block();
         }
         _ => {}
     }
 }
 
+/// The app's portal, reporting download progress to the UI.
+fn reporting_portal(app: &App, tx: &mpsc::UnboundedSender<AppEvent>) -> SharedPortal {
+    let tx = tx.clone();
+    app.portal.with_reporter(Arc::new(move |progress| {
+        let _ = tx.send(AppEvent::DownloadProgress(progress));
+    }))
+}
+
 async fn do_install(
     mod_name: &str,
     factorio_version: &str,
-    username: &str,
-    token: &str,
+    portal: &SharedPortal,
+    credentials: &Credentials,
     mods_dir: &str,
     installed_map: &HashMap<String, semver::Version>,
+    lock_path: &Path,
 ) -> anyhow::Result<InstallResult> {
-    // Resolve dependencies
-    let fetch = |name: String| async move { api::fetch_mod_details(&name).await };
-    let resolve_result =
-        resolver::resolve(mod_name, factorio_version, installed_map, &fetch).await?;
-
-    let total = resolve_result.to_download.len();
-    let dep_count = if total > 0 { total - 1 } else { 0 };
-
-    // Download each mod sequentially
-    for (i, (_name, release)) in resolve_result.to_download.iter().enumerate() {
-        if let Err(e) = api::download_mod(release, username, token, mods_dir).await {
-            // Clean up partial download is handled inside download_mod
-            return Err(anyhow::anyhow!(
-                "Failed to download '{}' ({}/{} downloaded before failure): {}",
-                _name,
-                i,
-                total,
-                e
-            ));
-        }
+    // The lockfile is part of the install: if it cannot be written, the
+    // downloads are rolled back
+    let mut transaction = Transaction::begin(mods_dir)?;
+    let result = async {
+        let downloaded = installer::install(
+            &mut transaction,
+            portal,
+            mod_name,
+            &semver::VersionReq::STAR,
+            factorio_version,
+            credentials,
+            installed_map,
+        )
+        .await?;
+        lockfile::write(mods_dir, lock_path)?;
+        Ok(downloaded)
     }
+    .await;
+    let downloaded = transaction.finish(result)?;
+
+    let dep_count = downloaded.len().saturating_sub(1);
 
     // Re-read installed mods
     let installed_mods = installed::read_installed_mods(mods_dir)?;
@@ -380,4 +670,144 @@ async fn do_install(
     })
 }
 
+async fn do_sync(
+    factorio_version: &str,
+    portal: &SharedPortal,
+    credentials: &Credentials,
+    mods_dir: &str,
+    lock_path: &Path,
+) -> anyhow::Result<SyncResult> {
+    let report =
+        installer::sync(portal, factorio_version, credentials, mods_dir, lock_path).await?;
+
+    Ok(SyncResult {
+        report,
+        installed_mods: installed::read_installed_mods(mods_dir)?,
+        mod_list: ModList::load_or_create(mods_dir)?,
+    })
+}
+
+async fn do_update(
+    mod_name: &str,
+    factorio_version: &str,
+    portal: &SharedPortal,
+    credentials: &Credentials,
+    mods_dir: &str,
+    lock_path: &Path,
+) -> anyhow::Result<UpdateResult> {
+    let report = installer::update(
+        portal,
+        &[mod_name.to_string()],
+        factorio_version,
+        credentials,
+        mods_dir,
+        lock_path,
+    )
+    .await?;
+
+    Ok(UpdateResult {
+        report,
+        installed_mods: installed::read_installed_mods(mods_dir)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Tab;
+    use crate::factorio::credentials::Source;
+    use crate::factorio::fake_portal::MemoryPortal;
+    use crate::factorio::test_support;
+
+    fn make_app(portal: MemoryPortal, dir: &Path) -> App {
+        let mods_dir = dir.join("mods");
+        std::fs::create_dir_all(&mods_dir).unwrap();
+        let credentials = Credentials {
+            username: "user".to_string(),
+            token: "token".to_string(),
+        };
+        App::new(
+            "1.1".to_string(),
+            Some((credentials, Source::Environment)),
+            Arc::new(portal),
+            mods_dir.to_str().unwrap().to_string(),
+            dir.join("fml.lock"),
+        )
+    }
+
+    fn key(code: KeyCode) -> AppEvent {
+        AppEvent::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    /// Handle `event` and everything the tasks it spawns send back, up to and
+    /// including the first event `last` matches.
+    async fn run(app: &mut App, event: AppEvent, last: fn(&AppEvent) -> bool) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        handle_event(event, app, tx.clone());
+        while let Some(event) = rx.recv().await {
+            let done = last(&event);
+            handle_event(event, app, tx.clone());
+            if done {
+                break;
+            }
+        }
+    }
+
+    fn status(app: &App) -> &str {
+        app.status_message.as_ref().map(|(s, _)| s.as_str()).unwrap_or("")
+    }
+
+    #[tokio::test]
+    async fn installs_the_selected_mod_with_its_dependencies() {
+        let portal = MemoryPortal::new()
+            .with_release("lib", "1.0.0", &["base >= 1.1.0"])
+            .with_release("mod-a", "1.0.0", &["base >= 1.1.0", "lib >= 1.0.0"]);
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = make_app(portal, dir.path());
+
+        let list = app.portal.fetch_mod_list("1.1").await;
+        let (tx, _rx) = mpsc::unbounded_channel();
+        handle_event(AppEvent::ModListLoaded(list), &mut app, tx);
+        app.select_tab(Tab::Install);
+        app.install_selected = app
+            .filtered_install_mods()
+            .iter()
+            .position(|m| m.name == "mod-a");
+
+        run(&mut app, key(KeyCode::Enter), |e| matches!(e, AppEvent::ModInstalled(_))).await;
+
+        assert_eq!(status(&app), "Installed mod-a + 1 dependencies");
+        assert!(!app.installing && app.downloads.is_empty());
+        let names: Vec<&str> = app
+            .manage_mods
+            .iter()
+            .map(|m| m.installed_mod.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["lib", "mod-a"]);
+        assert!(app.manage_mods.iter().all(|m| m.enabled && m.pending));
+        assert!(dir.path().join("fml.lock").is_file());
+    }
 
+    #[tokio::test]
+    async fn updates_the_selected_mod() {
+        let portal = MemoryPortal::new()
+            .with_release("lib", "1.0.0", &[])
+            .with_release("lib", "1.1.0", &[]);
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = make_app(portal, dir.path());
+        let mods_dir = dir.path().join("mods");
+        test_support::write_mod_zip(&mods_dir, "lib", "1.0.0", &[]);
+
+        let loaded = installed::read_installed_mods(&app.mods_dir)
+            .map(|mods| (mods, ModList::load_or_create(&app.mods_dir).unwrap()));
+        let (tx, _rx) = mpsc::unbounded_channel();
+        handle_event(AppEvent::InstalledModsLoaded(loaded), &mut app, tx);
+
+        run(&mut app, key(KeyCode::Char('u')), |e| matches!(e, AppEvent::ModsUpdated(_))).await;
+
+        assert_eq!(status(&app), "Updated lib 1.0.0 -> 1.1.0");
+        assert_eq!(app.manage_mods[0].installed_mod.version, semver::Version::new(1, 1, 0));
+        assert!(mods_dir.join("lib_1.1.0.zip").is_file());
+        assert!(!mods_dir.join("lib_1.0.0.zip").exists());
+    }
+}
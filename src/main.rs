@@ -1,4 +1,5 @@
 mod app;
+mod cli;
 mod config;
 mod event;
 mod factorio;
@@ -14,17 +15,26 @@ use crossterm::{
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 
 use app::App;
 use event::{spawn_event_loop, AppEvent};
-use factorio::{
-    installed, mod_list::ModList, types,
-};
+use factorio::{installed, installer::RemoveMode, mod_list::ModList, mod_settings};
 
 #[derive(Parser)]
 #[command(name = "fml", about = "Factorio Mod Manager for headless servers")]
 struct Cli {
+    /// Path to fml.json (default: ./fml.json, then $XDG_CONFIG_HOME/fml/fml.json)
+    #[arg(long, global = true, env = "FML_CONFIG")]
+    config: Option<PathBuf>,
+    /// Named instance from the `instances` section of fml.json
+    #[arg(long, short, global = true, env = "FML_INSTANCE")]
+    instance: Option<String>,
+    /// Use only cached mod portal data; nothing is downloaded
+    #[arg(long, global = true, env = "FML_OFFLINE")]
+    offline: bool,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -32,29 +42,208 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize FML configuration
-    Init,
+    Init {
+        /// Factorio mods directory (discovered when omitted)
+        #[arg(long, env = "FML_MODS_DIR")]
+        mods_dir: Option<String>,
+        /// Path to server-settings.json (discovered when omitted)
+        #[arg(long, env = "FML_SERVER_SETTINGS")]
+        server_settings: Option<String>,
+        /// Use discovered paths without prompting
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Install mods and their dependencies without opening the TUI
+    Install {
+        /// Mods to install, optionally pinned as `name@version`
+        #[arg(required_unless_present = "locked", conflicts_with = "locked")]
+        mods: Vec<String>,
+        /// Reproduce exactly the mod set recorded in fml.lock
+        #[arg(long)]
+        locked: bool,
+    },
+    /// Download every mod listed in mod-list.json that is not installed yet
+    Sync,
+    /// Upgrade installed mods to their newest compatible release
+    Update {
+        /// Mods to update (all installed mods when omitted)
+        mods: Vec<String>,
+    },
+    /// Show installed mods that have a newer compatible release
+    Outdated {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the changes needed to match the mods declared in fml.json
+    Plan,
+    /// Install, remove, upgrade and toggle mods to match fml.json
+    Apply,
+    /// Check the mods directory for problems that stop Factorio from starting
+    Doctor,
+    /// Install exactly the mod versions a save was made with and enable only those
+    FromSave {
+        /// Path to the save zip
+        save: PathBuf,
+    },
+    /// Print the dependency tree of the installed mods
+    Tree {
+        /// Only show the tree below this mod
+        r#mod: Option<String>,
+    },
+    /// Show which installed mods require a mod
+    Why { r#mod: String },
+    /// Remove installed mods and their mod-list.json entries
+    Remove {
+        /// Mods to remove
+        #[arg(required = true)]
+        mods: Vec<String>,
+        /// Remove even if enabled mods still require them
+        #[arg(long, conflicts_with = "cascade")]
+        force: bool,
+        /// Also remove every enabled mod that requires them
+        #[arg(long)]
+        cascade: bool,
+    },
+    /// Manage the cache of mod portal data and mod zips
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Serve a fixture directory as a local mod portal, for testing offline
+    #[command(hide = true)]
+    StubPortal {
+        /// Directory with api/mods.json, api/mods/<name>/full.json and download/<name>/<version>.zip
+        root: PathBuf,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8000")]
+        addr: std::net::SocketAddr,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Delete stored zips that no instance's mods directory uses
+    Prune,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let load_config = || {
+        let mut config = config::FmlConfig::load(cli.config.as_deref(), cli.instance.as_deref())?;
+        config.offline = cli.offline;
+        anyhow::Ok(config)
+    };
 
     match cli.command {
-        Some(Commands::Init) => {
-            config::FmlConfig::init()?;
+        Some(Commands::Init {
+            mods_dir,
+            server_settings,
+            yes,
+        }) => {
+            config::FmlConfig::init(
+                cli.config.as_deref(),
+                cli.instance.as_deref(),
+                mods_dir,
+                server_settings,
+                yes,
+            )?;
             return Ok(());
         }
+        Some(Commands::Install { mods, locked }) => {
+            let config = load_config()?;
+            if locked {
+                return cli::install_locked(&config, &config.shared_portal()?).await;
+            }
+            return cli::install(&config, &config.shared_portal()?, &mods).await;
+        }
+        Some(Commands::Sync) => {
+            let config = load_config()?;
+            return cli::sync(&config, &config.shared_portal()?).await;
+        }
+        Some(Commands::Update { mods }) => {
+            let config = load_config()?;
+            return cli::update(&config, &config.shared_portal()?, &mods).await;
+        }
+        Some(Commands::Outdated { json }) => {
+            let config = load_config()?;
+            return cli::outdated(&config, &config.shared_portal()?, json).await;
+        }
+        Some(Commands::Plan) => {
+            let config = load_config()?;
+            return cli::plan(&config, &config.shared_portal()?).await;
+        }
+        Some(Commands::Apply) => {
+            let config = load_config()?;
+            return cli::apply(&config, &config.shared_portal()?).await;
+        }
+        Some(Commands::Doctor) => {
+            let config = load_config()?;
+            return cli::doctor(&config);
+        }
+        Some(Commands::FromSave { save }) => {
+            let config = load_config()?;
+            return cli::from_save(&config, &config.shared_portal()?, &save).await;
+        }
+        Some(Commands::Tree { r#mod }) => {
+            let config = load_config()?;
+            return cli::tree(&config, r#mod.as_deref());
+        }
+        Some(Commands::Why { r#mod }) => {
+            let config = load_config()?;
+            return cli::why(&config, &r#mod);
+        }
+        Some(Commands::Remove {
+            mods,
+            force,
+            cascade,
+        }) => {
+            let mode = if cascade {
+                RemoveMode::Cascade
+            } else if force {
+                RemoveMode::Force
+            } else {
+                RemoveMode::Refuse
+            };
+            let config = load_config()?;
+            return cli::remove(&config, &mods, mode);
+        }
+        Some(Commands::Cache {
+            command: CacheCommands::Prune,
+        }) => {
+            let config = load_config()?;
+            return cli::cache_prune(&config);
+        }
+        Some(Commands::StubPortal { root, addr }) => {
+            return cli::stub_portal(&root, addr).await;
+        }
         None => {}
     }
 
     // Load config
-    let config = config::FmlConfig::load()?;
+    let config = load_config()?;
 
-    // Read server settings
-    let server_settings = types::read_server_settings(&config.server_config_path)?;
+    // Find credentials; without them the TUI only browses
+    let credentials = config.credentials()?;
+    match &credentials {
+        Some((_, source)) => eprintln!("Using Factorio credentials from {}", source),
+        None => eprintln!(
+            "{}. Starting read-only.",
+            factorio::credentials::MISSING_HINT
+        ),
+    }
+    let portal = config.portal()?;
+    if !config.mirrors.is_empty() {
+        let urls: Vec<&str> = portal.mirrors().iter().map(|m| m.url.as_str()).collect();
+        eprintln!("Using mod portal mirrors: {}", urls.join(", "));
+    }
+    if portal.is_offline() {
+        eprintln!("Offline: using cached mod portal data only");
+    }
 
     // Detect Factorio version
-    let factorio_version = types::detect_factorio_version(&config.mods_dir_path)?;
+    let factorio_version = config.factorio_version()?;
     eprintln!("Detected Factorio version: {}", factorio_version);
 
     // Set up panic hook to restore terminal
@@ -72,25 +261,46 @@ async fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app
+    // Create event channel
+    let (tx, mut rx) = mpsc::unbounded_channel::<AppEvent>();
+
+    // Create app; portal warnings go to the status bar, not over the screen
+    let warnings = tx.clone();
+    let portal = portal.with_warnings(move |message| {
+        let _ = warnings.send(AppEvent::Warning(message));
+    });
     let mut app = App::new(
         factorio_version.clone(),
-        server_settings,
+        credentials,
+        Arc::new(portal),
         config.mods_dir_path.clone(),
+        config.lock_path(),
     );
 
-    // Create event channel
-    let (tx, mut rx) = mpsc::unbounded_channel::<AppEvent>();
-
     // Spawn terminal event loop
     spawn_event_loop(tx.clone());
 
+    // Spawn initial async task: check that the token works
+    if let Some(creds) = app.credentials.clone().filter(|_| !app.portal.is_offline()) {
+        let portal = app.portal.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = portal.validate_credentials(&creds).await;
+            let _ = tx.send(AppEvent::CredentialsChecked(result));
+        });
+    }
+
     // Spawn initial async task: fetch mod list
     {
         let fv = factorio_version.clone();
+        let portal = app.portal.clone();
         let tx = tx.clone();
         tokio::spawn(async move {
-            let result = factorio::api::fetch_mod_list(&fv).await;
+            // Show the cached list right away, then revalidate it
+            if let Some(cached) = portal.cached_mod_list(&fv) {
+                let _ = tx.send(AppEvent::ModListLoaded(Ok(cached)));
+            }
+            let result = portal.fetch_mod_list(&fv).await;
             let _ = tx.send(AppEvent::ModListLoaded(result));
         });
     }
@@ -109,6 +319,28 @@ async fn main() -> Result<()> {
         });
     }
 
+    // Spawn initial async task: read mod settings
+    {
+        let mods_dir = config.mods_dir_path.clone();
+        let fv = factorio_version.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = (|| -> anyhow::Result<_> {
+                let mods = installed::read_installed_mods(&mods_dir)?;
+                let warn = |message| {
+                    let _ = tx.send(AppEvent::Warning(message));
+                };
+                let settings: Vec<_> = mod_settings::read_prototypes(&mods_dir, &mods, warn)
+                    .into_iter()
+                    .filter(|s| s.scope != mod_settings::SettingScope::RuntimePerUser)
+                    .collect();
+                let stored = mod_settings::ModSettings::load_or_create(&mods_dir, &fv)?;
+                Ok((settings, stored))
+            })();
+            let _ = tx.send(AppEvent::SettingsLoaded(result));
+        });
+    }
+
     // Main loop
     loop {
         terminal.draw(|frame| ui::draw(&app, frame))?;
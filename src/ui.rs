@@ -1,10 +1,14 @@
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Tabs};
+use ratatui::widgets::{
+    Block, Borders, Clear, LineGauge, List, ListItem, ListState, Paragraph, Tabs,
+};
 use ratatui::Frame;
 
 use crate::app::{ActiveBlock, App, Tab};
+use crate::factorio::api::Progress;
+use crate::factorio::mod_settings::SettingScope;
 
 pub fn draw(app: &App, frame: &mut Frame) {
     let chunks = Layout::default()
@@ -12,7 +16,7 @@ pub fn draw(app: &App, frame: &mut Frame) {
         .constraints([
             Constraint::Length(3), // tab bar
             Constraint::Min(1),   // main content
-            Constraint::Length(1), // status bar
+            Constraint::Length(status_height(app)), // status bar
         ])
         .split(frame.area());
 
@@ -21,6 +25,7 @@ pub fn draw(app: &App, frame: &mut Frame) {
     match app.tab {
         Tab::Manage => draw_manage_tab(app, frame, chunks[1]),
         Tab::Install => draw_install_tab(app, frame, chunks[1]),
+        Tab::Settings => draw_settings_tab(app, frame, chunks[1]),
     }
 
     draw_status_bar(app, frame, chunks[2]);
@@ -34,15 +39,21 @@ fn draw_tabs(app: &App, frame: &mut Frame, area: Rect) {
     let titles: Vec<Line> = vec![
         Line::from(" Manage "),
         Line::from(" Install "),
+        Line::from(" Settings "),
     ];
 
     let selected = match app.tab {
         Tab::Manage => 0,
         Tab::Install => 1,
+        Tab::Settings => 2,
     };
 
     let tabs = Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL).title(" FML "))
+        .block(Block::default().borders(Borders::ALL).title(if app.credentials.is_some() {
+            " FML "
+        } else {
+            " FML (read-only) "
+        }))
         .select(selected)
         .style(Style::default().fg(Color::White))
         .highlight_style(
@@ -291,17 +302,161 @@ fn draw_install_tab(app: &App, frame: &mut Frame, area: Rect) {
     frame.render_stateful_widget(list, chunks[1], &mut state);
 }
 
+fn draw_settings_tab(app: &App, frame: &mut Frame, area: Rect) {
+    let title = if app.settings_dirty {
+        " Mod Settings (unsaved, Ctrl+S to save) "
+    } else {
+        " Mod Settings "
+    };
+
+    if app.mod_settings.is_none() || app.settings.is_empty() {
+        let msg = if app.mod_settings.is_none() {
+            "Loading mod settings..."
+        } else {
+            "No startup or map settings found in installed mods"
+        };
+        let empty = Paragraph::new(msg)
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let editing = app.active_block == ActiveBlock::SettingsEdit;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(if editing {
+            vec![Constraint::Min(1), Constraint::Length(3)]
+        } else {
+            vec![Constraint::Min(1)]
+        })
+        .split(area);
+
+    let items: Vec<ListItem> = app
+        .settings
+        .iter()
+        .map(|proto| {
+            let scope = match proto.scope {
+                SettingScope::Startup => "startup",
+                _ => "map",
+            };
+            let value = app
+                .setting_value(proto)
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "?".to_string());
+            let is_default = app.setting_value(proto) == proto.default;
+            let mut spans = vec![
+                Span::styled(format!("[{}] ", scope), Style::default().fg(Color::DarkGray)),
+                Span::raw(format!("{} / {} = ", proto.mod_name, proto.name)),
+                Span::styled(
+                    value,
+                    if is_default {
+                        Style::default()
+                    } else {
+                        Style::default().fg(Color::Cyan)
+                    },
+                ),
+            ];
+            if !is_default {
+                if let Some(default) = &proto.default {
+                    spans.push(Span::styled(
+                        format!(" (default: {})", default),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let border_style = if app.active_block == ActiveBlock::SettingsList {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(border_style),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▸ ");
+
+    let mut state = ListState::default();
+    state.select(app.settings_selected);
+    frame.render_stateful_widget(list, chunks[0], &mut state);
+
+    if editing {
+        let proto = app.settings_selected.and_then(|sel| app.settings.get(sel));
+        let hint = match proto {
+            Some(p) if !p.allowed_values.is_empty() => {
+                format!(" {} ({}) ", p.name, p.allowed_values.join(", "))
+            }
+            Some(p) if p.minimum.is_some() || p.maximum.is_some() => format!(
+                " {} ({} to {}) ",
+                p.name,
+                p.minimum.map_or("-∞".to_string(), |m| m.to_string()),
+                p.maximum.map_or("∞".to_string(), |m| m.to_string())
+            ),
+            Some(p) => format!(" {} ", p.name),
+            None => String::new(),
+        };
+        let input = Paragraph::new(app.settings_input.as_str()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(hint)
+                .border_style(Style::default().fg(Color::Yellow)),
+        );
+        frame.render_widget(input, chunks[1]);
+        frame.set_cursor_position((
+            chunks[1].x + 1 + app.settings_input.chars().count() as u16,
+            chunks[1].y + 1,
+        ));
+    }
+}
+
+/// Most downloads shown at once; the rest appear as others finish.
+const MAX_GAUGES: usize = 5;
+
+fn status_height(app: &App) -> u16 {
+    app.downloads.len().clamp(1, MAX_GAUGES) as u16
+}
+
 fn draw_status_bar(app: &App, frame: &mut Frame, area: Rect) {
+    if !app.downloads.is_empty() {
+        for (row, progress) in app.downloads.iter().take(MAX_GAUGES).enumerate() {
+            let line = Rect {
+                y: area.y + row as u16,
+                height: 1,
+                ..area
+            };
+            draw_download_gauge(progress, frame, line);
+        }
+        return;
+    }
+
     let text = if let Some((msg, _)) = &app.status_message {
         Span::styled(msg.as_str(), Style::default().fg(Color::Yellow))
     } else {
         let hints = match app.tab {
             Tab::Manage => {
-                "Tab: switch tabs | ↑↓: navigate | Enter: toggle | d: delete | Ctrl+S: save | Ctrl+C: quit"
+                "Tab: switch tabs | ↑↓: navigate | Enter: toggle | d: delete | u: update | s: sync mod-list.json | Ctrl+S: save | Ctrl+C: quit"
             }
             Tab::Install => {
                 "Tab: switch tabs | ↑↓: navigate | Enter: install | /: search | Ctrl+S: save | Ctrl+C: quit"
             }
+            Tab::Settings if app.active_block == ActiveBlock::SettingsEdit => {
+                "Enter: apply | Esc: cancel"
+            }
+            Tab::Settings => {
+                "Tab: switch tabs | ↑↓: navigate | Enter: toggle/edit | r: reset to default | Ctrl+S: save | Ctrl+C: quit"
+            }
         };
         Span::styled(hints, Style::default().fg(Color::DarkGray))
     };
@@ -310,6 +465,29 @@ fn draw_status_bar(app: &App, frame: &mut Frame, area: Rect) {
     frame.render_widget(paragraph, area);
 }
 
+fn draw_download_gauge(progress: &Progress, frame: &mut Frame, area: Rect) {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let downloaded = progress.downloaded as f64 / MIB;
+    let (ratio, label) = match progress.total {
+        Some(total) if total > 0 => (
+            (progress.downloaded as f64 / total as f64).min(1.0),
+            format!(
+                "{} {:.1}/{:.1} MiB",
+                progress.file_name,
+                downloaded,
+                total as f64 / MIB
+            ),
+        ),
+        _ => (0.0, format!("{} {:.1} MiB", progress.file_name, downloaded)),
+    };
+
+    let gauge = LineGauge::default()
+        .filled_style(Style::default().fg(Color::Green))
+        .label(label)
+        .ratio(ratio);
+    frame.render_widget(gauge, area);
+}
+
 fn draw_quit_popup(frame: &mut Frame) {
     let area = centered_rect(40, 7, frame.area());
     frame.render_widget(Clear, area);